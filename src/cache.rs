@@ -0,0 +1,459 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use md5::{Digest, Md5};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::config::Settings;
+use crate::error::Result;
+
+
+/// State kept across calls made through the same `Client`.
+#[derive(Debug, Default)]
+pub struct Cache {
+    /// By provider id. `None` until read from `keys_cache_path`.
+    keys: Mutex<Option<HashMap<String, CachedKeys>>>,
+    /// Held while keys are fetched, so that concurrent calls on a cold cache wait for one fetch.
+    keys_fetch: Mutex<()>,
+    responses: Mutex<Responses>,
+    /// `cache_dir` once pruned of what earlier runs left behind.
+    pruned_dir: Mutex<Option<String>>,
+}
+
+/// Keys a stream provider extracted from its player, as kept in memory and on disk.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedKeys {
+    /// Host they were extracted from, so that another host never gets them.
+    base_url: String,
+    /// Unix time in milliseconds.
+    fetched_at: u64,
+    keys: Value,
+}
+
+/// Scrapers whose results go through the response cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Endpoint {
+    Search,
+    EpisodeList,
+    TitlePage,
+}
+
+impl Endpoint {
+    const ALL: [Endpoint; 3] = [Endpoint::Search, Endpoint::EpisodeList, Endpoint::TitlePage];
+
+    pub fn ttl_ms(&self, settings: &Settings) -> u64 {
+        match self {
+            Endpoint::Search => settings.search_cache_ttl_ms,
+            Endpoint::EpisodeList => settings.episode_list_cache_ttl_ms,
+            Endpoint::TitlePage => settings.title_page_cache_ttl_ms,
+        }
+    }
+
+    /// Base url the responses of this endpoint are scraped from, which scopes their cache entries.
+    pub fn base_url<'a>(&self, settings: &'a Settings) -> &'a str {
+        match self {
+            Endpoint::Search | Endpoint::EpisodeList | Endpoint::TitlePage => &settings.source_base_url,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Endpoint::Search => "search",
+            Endpoint::EpisodeList => "episode_list",
+            Endpoint::TitlePage => "title_page",
+        }
+    }
+}
+
+/// A scraper result, as kept in memory and on disk.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedResponse {
+    endpoint: Endpoint,
+    /// `Endpoint::base_url` when it was fetched, so that another host never gets it.
+    #[serde(default)]
+    base_url: String,
+    key: String,
+    /// Unix time in milliseconds.
+    stored_at: u64,
+    value: Value,
+}
+
+impl CachedResponse {
+    fn id(&self) -> (Endpoint, String) {
+        (self.endpoint, scoped_key(&self.base_url, &self.key))
+    }
+}
+
+/// In-memory LRU of scraper results, keyed by endpoint and host-scoped normalised arguments.
+#[derive(Debug, Default)]
+struct Responses {
+    entries: HashMap<(Endpoint, String), (CachedResponse, u64)>,
+    /// Bumped on every access; the entry with the lowest tick is evicted first.
+    tick: u64,
+}
+
+impl Responses {
+    fn get(&mut self, endpoint: Endpoint, scoped_key: &str) -> Option<CachedResponse> {
+        self.tick += 1;
+        let tick = self.tick;
+
+        self.entries.get_mut(&(endpoint, scoped_key.to_string())).map(|(cached, last_used)| {
+            *last_used = tick;
+            cached.clone()
+        })
+    }
+
+    fn insert(&mut self, cached: CachedResponse, capacity: usize) {
+        self.tick += 1;
+        self.entries.insert(cached.id(), (cached, self.tick));
+
+        while self.entries.len() > capacity {
+            let Some(oldest) = self.entries.iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(id, _)| id.clone()) else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+    }
+}
+
+/// One line of `get_cache_info`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheEntryInfo {
+    pub endpoint: Endpoint,
+    pub base_url: String,
+    pub key: String,
+    pub age_ms: u64,
+    pub expires_in_ms: u64,
+}
+
+impl Cache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keys `provider` extracted from `base_url` less than `keys_ttl_ms` ago, from memory or else from `keys_cache_path`.
+    pub(crate) fn keys<T: DeserializeOwned>(&self, settings: &Settings, provider: &str, base_url: &str) -> Option<T> {
+        let keys = self.lock_keys(settings);
+
+        match keys.as_ref().and_then(|keys| keys.get(provider)) {
+            Some(cached) if is_fresh(cached, settings, base_url) => serde_json::from_value(cached.keys.clone()).ok(),
+            _ => None,
+        }
+    }
+
+    /// The cached keys of `provider`, or else the ones `fetch` extracts, cached in turn; `true` when they were cached.
+    ///
+    /// Keys are fetched one caller at a time: whoever waited for a fetch gets its keys instead of fetching again.
+    pub(crate) fn keys_or_fetch<T, F>(&self, settings: &Settings, provider: &str, base_url: &str, fetch: F) -> Result<(T, bool)>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce() -> Result<T>,
+    {
+        if let Some(keys) = self.keys(settings, provider, base_url) {
+            return Ok((keys, true));
+        }
+
+        let _fetching = self.keys_fetch.lock().unwrap_or_else(|e| e.into_inner());
+
+        if let Some(keys) = self.keys(settings, provider, base_url) {
+            return Ok((keys, true));
+        }
+
+        let keys = fetch()?;
+        self.store_keys(settings, provider, base_url, &keys);
+
+        Ok((keys, false))
+    }
+
+    pub(crate) fn store_keys<T: Serialize>(&self, settings: &Settings, provider: &str, base_url: &str, keys: &T) {
+        if settings.keys_ttl_ms == 0 {
+            return;
+        }
+
+        let Ok(keys) = serde_json::to_value(keys) else {
+            return;
+        };
+
+        let mut all_keys = self.lock_keys(settings);
+        let all_keys = all_keys.get_or_insert_with(HashMap::new);
+        all_keys.insert(provider.to_string(), CachedKeys {
+            base_url: base_url.to_string(),
+            fetched_at: now_ms(),
+            keys,
+        });
+
+        if let Some(path) = settings.keys_cache_path.as_deref() {
+            write_json(Path::new(path), all_keys);
+        }
+    }
+
+    /// Drop the keys of `provider`, or of every provider, everywhere, e.g. once the player stopped accepting them.
+    pub(crate) fn invalidate_keys(&self, settings: &Settings, provider: Option<&str>) {
+        let mut all_keys = self.lock_keys(settings);
+        let all_keys = all_keys.get_or_insert_with(HashMap::new);
+
+        match provider {
+            Some(provider) => {
+                all_keys.remove(provider);
+            },
+            None => all_keys.clear(),
+        }
+
+        if let Some(path) = settings.keys_cache_path.as_deref() {
+            match all_keys.is_empty() {
+                true => {
+                    let _ = fs::remove_file(path);
+                },
+                false => write_json(Path::new(path), all_keys),
+            }
+        }
+    }
+
+    /// The keys of every provider, read from `keys_cache_path` on first use.
+    fn lock_keys(&self, settings: &Settings) -> MutexGuard<'_, Option<HashMap<String, CachedKeys>>> {
+        let mut keys = self.keys.lock().unwrap_or_else(|e| e.into_inner());
+
+        if keys.is_none() {
+            *keys = settings.keys_cache_path.as_deref().and_then(read_json);
+        }
+
+        keys
+    }
+
+    /// Return the cached result of `endpoint` for `key`, or run `fetch` and cache what it returns.
+    ///
+    /// `bypass` skips the lookup but still refreshes the cache with the new result.
+    pub fn get_or_fetch<T, F>(&self, settings: &Settings, endpoint: Endpoint, key: &str, bypass: bool, fetch: F) -> Result<T>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce() -> Result<T>,
+    {
+        let ttl_ms = endpoint.ttl_ms(settings);
+        if ttl_ms == 0 || settings.cache_capacity == 0 {
+            return fetch();
+        }
+
+        if !bypass
+            && let Some(value) = self.lookup(settings, endpoint, key)
+            && let Ok(value) = serde_json::from_value(value) {
+            return Ok(value);
+        }
+
+        let value = fetch()?;
+
+        if let Ok(json) = serde_json::to_value(&value) {
+            let cached = CachedResponse {
+                endpoint,
+                base_url: endpoint.base_url(settings).to_string(),
+                key: key.to_string(),
+                stored_at: now_ms(),
+                value: json,
+            };
+
+            if let Some(dir) = settings.cache_dir.as_deref() {
+                write_json(&response_path(dir, endpoint, &cached.id().1), &cached);
+                prune_dir(dir, settings);
+            }
+
+            self.responses.lock().unwrap_or_else(|e| e.into_inner()).insert(cached, settings.cache_capacity);
+        }
+
+        Ok(value)
+    }
+
+    /// Fresh cached result of `endpoint` for `key` on the configured host, from memory or else from `cache_dir`.
+    ///
+    /// A stale entry is dropped, file included.
+    pub fn lookup(&self, settings: &Settings, endpoint: Endpoint, key: &str) -> Option<Value> {
+        self.prune_dir_once(settings);
+
+        let ttl_ms = endpoint.ttl_ms(settings);
+        let scoped_key = scoped_key(endpoint.base_url(settings), key);
+        let path = settings.cache_dir.as_deref().map(|dir| response_path(dir, endpoint, &scoped_key));
+        let mut responses = self.responses.lock().unwrap_or_else(|e| e.into_inner());
+
+        let cached = responses.get(endpoint, &scoped_key).or_else(|| {
+            let cached: CachedResponse = read_json(path.as_ref()?)?;
+            responses.insert(cached.clone(), settings.cache_capacity);
+            Some(cached)
+        })?;
+
+        if now_ms().saturating_sub(cached.stored_at) < ttl_ms {
+            return Some(cached.value);
+        }
+
+        responses.entries.remove(&(endpoint, scoped_key));
+        if let Some(path) = path {
+            let _ = fs::remove_file(path);
+        }
+
+        None
+    }
+
+    /// Prune `cache_dir` the first time it is used, which cleans up after the runs before this one.
+    fn prune_dir_once(&self, settings: &Settings) {
+        let Some(dir) = settings.cache_dir.as_deref() else {
+            return;
+        };
+
+        let mut pruned_dir = self.pruned_dir.lock().unwrap_or_else(|e| e.into_inner());
+        if pruned_dir.as_deref() != Some(dir) {
+            prune_dir(dir, settings);
+            *pruned_dir = Some(dir.to_string());
+        }
+    }
+
+    /// Everything currently held in memory, most recently used first.
+    pub fn entries(&self, settings: &Settings) -> Vec<CacheEntryInfo> {
+        let responses = self.responses.lock().unwrap_or_else(|e| e.into_inner());
+        let now = now_ms();
+
+        let mut entries = responses.entries.values().collect::<Vec<_>>();
+        entries.sort_by_key(|(_, last_used)| Reverse(*last_used));
+
+        entries.into_iter()
+            .map(|(cached, _)| {
+                let age_ms = now.saturating_sub(cached.stored_at);
+                CacheEntryInfo {
+                    endpoint: cached.endpoint,
+                    base_url: cached.base_url.clone(),
+                    key: cached.key.clone(),
+                    age_ms,
+                    expires_in_ms: cached.endpoint.ttl_ms(settings).saturating_sub(age_ms),
+                }
+            })
+            .collect()
+    }
+
+    /// Drop the cached results of `endpoint`, or of every endpoint along with the keys, from memory and disk.
+    pub fn clear(&self, settings: &Settings, endpoint: Option<Endpoint>) {
+        self.responses.lock().unwrap_or_else(|e| e.into_inner())
+            .entries
+            .retain(|(cached_endpoint, _), _| endpoint.is_some_and(|endpoint| endpoint != *cached_endpoint));
+
+        // `cache_dir` may be shared, so only files named like `response_path` names them are ours to delete.
+        if let Some(dir) = settings.cache_dir.as_deref()
+            && let Ok(files) = fs::read_dir(dir) {
+            for file in files.flatten() {
+                let name = file.file_name().to_string_lossy().into_owned();
+                let is_ours = Endpoint::ALL.into_iter()
+                    .filter(|ours| endpoint.is_none_or(|endpoint| endpoint == *ours))
+                    .any(|ours| is_response_file(&name, ours));
+
+                if is_ours {
+                    let _ = fs::remove_file(file.path());
+                }
+            }
+        }
+
+        if endpoint.is_none() {
+            self.invalidate_keys(settings, None);
+        }
+    }
+}
+
+fn is_fresh(cached: &CachedKeys, settings: &Settings, base_url: &str) -> bool {
+    cached.base_url == base_url
+        && now_ms().saturating_sub(cached.fetched_at) < settings.keys_ttl_ms
+}
+
+/// Normalise free text arguments so that `Loki`, ` loki ` and `LOKI` share an entry.
+pub fn normalize_key(key: &str) -> String {
+    key.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+const RESPONSE_FILE_PREFIX: &str = "moviesapi-";
+
+fn scoped_key(base_url: &str, key: &str) -> String {
+    format!("{} {}", base_url, key)
+}
+
+fn response_path(dir: &str, endpoint: Endpoint, scoped_key: &str) -> PathBuf {
+    let digest = Md5::digest(scoped_key.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+
+    Path::new(dir).join(format!("{}{}-{}.json", RESPONSE_FILE_PREFIX, endpoint.as_str(), digest))
+}
+
+/// Whether `name` is a file `response_path` would name for `endpoint`: `moviesapi-{endpoint}-{md5}.json`.
+fn is_response_file(name: &str, endpoint: Endpoint) -> bool {
+    name.strip_prefix(RESPONSE_FILE_PREFIX)
+        .and_then(|name| name.strip_prefix(endpoint.as_str()))
+        .and_then(|name| name.strip_prefix('-'))
+        .and_then(|name| name.strip_suffix(".json"))
+        .is_some_and(|digest| digest.len() == 32 && digest.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Delete the response files in `dir` that outlived their endpoint's TTL, then the oldest ones past `cache_capacity`,
+/// so that the directory stays as bounded as the in-memory cache, whichever run wrote them.
+fn prune_dir(dir: &str, settings: &Settings) {
+    let Ok(files) = fs::read_dir(dir) else {
+        return;
+    };
+
+    let now = SystemTime::now();
+    let mut kept = Vec::new();
+
+    for file in files.flatten() {
+        let name = file.file_name().to_string_lossy().into_owned();
+        let Some(endpoint) = Endpoint::ALL.into_iter().find(|endpoint| is_response_file(&name, *endpoint)) else {
+            continue;
+        };
+
+        let modified = file.metadata().and_then(|metadata| metadata.modified()).unwrap_or(UNIX_EPOCH);
+        let age_ms = now.duration_since(modified).map(|age| age.as_millis() as u64).unwrap_or_default();
+
+        match age_ms < endpoint.ttl_ms(settings) {
+            true => kept.push((modified, file.path())),
+            false => {
+                let _ = fs::remove_file(file.path());
+            },
+        }
+    }
+
+    kept.sort_by_key(|(modified, _)| Reverse(*modified));
+    for (_, path) in kept.into_iter().skip(settings.cache_capacity) {
+        let _ = fs::remove_file(path);
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+// The disk copies are only an optimisation: failing to read or write them never fails a call.
+
+fn read_json<T: DeserializeOwned>(path: impl AsRef<Path>) -> Option<T> {
+    let raw = fs::read_to_string(path).ok()?;
+
+    serde_json::from_str(&raw).ok()
+}
+
+fn write_json<T: Serialize>(path: &Path, value: &T) {
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+
+    match serde_json::to_string(value) {
+        Ok(raw) => {
+            if let Err(e) = fs::write(path, raw) {
+                eprintln!("[Cache] Failed to write {}: {}", path.display(), e);
+            }
+        },
+        Err(e) => eprintln!("[Cache] Failed to serialize {}: {}", path.display(), e),
+    }
+}
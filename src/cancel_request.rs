@@ -0,0 +1,12 @@
+use crate::runtime;
+
+
+/// Cancel the async call behind `handle`, abandoning any request it has in flight.
+///
+/// Its callback still fires, promptly and with a `CANCELLED` envelope.
+///
+/// Returns `false` if the call already finished or the handle is unknown.
+#[unsafe(no_mangle)]
+pub extern "C" fn cancel_request(handle: u64) -> bool {
+    runtime::cancel(handle)
+}
@@ -0,0 +1,45 @@
+use std::os::raw::{c_char};
+
+use serde::{Deserialize, Serialize};
+
+use crate::cache::Endpoint;
+use crate::client::Client;
+use crate::utils::ffi;
+
+
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ReturnResult {
+    status: bool,
+    message: String,
+    data: Option<Endpoint>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Arguments {
+    /// Only clear this endpoint. Omitted to clear every cached response and the moviesapi keys.
+    #[serde(default)]
+    endpoint: Option<Endpoint>,
+}
+
+
+/// Drop cached responses from memory and from `cache_dir`. `{}` clears everything.
+#[unsafe(no_mangle)]
+pub extern "C" fn clear_cache(
+    arguments_ptr : *const c_char,
+) -> *const c_char {
+    ffi::respond("Clear Cache", || {
+        let args: Arguments = ffi::parse_arguments(arguments_ptr)?;
+
+        // ================================================
+
+        let client = Client::shared()?;
+        client.cache().clear(client.settings(), args.endpoint);
+
+        Ok(ReturnResult {
+            status: true,
+            message: String::from("Success"),
+            data: args.endpoint,
+        })
+    })
+}
@@ -0,0 +1,376 @@
+use std::fmt;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+
+use reqwest::Method;
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, USER_AGENT};
+use serde_json::Value;
+
+use crate::cache::Cache;
+use crate::config::{self, Settings};
+use crate::error::{Error, Result};
+use crate::runtime::{self, CancelToken};
+use crate::utils::http::header_value;
+
+
+/// How often a cancellable client checks its token while waiting on the network.
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+
+/// A single outgoing HTTP request.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: Method,
+    pub url: String,
+    pub headers: HeaderMap,
+    pub body: Option<Value>,
+    /// Time left for this attempt, set by the `Client` from its settings and deadline.
+    pub timeout: Option<Duration>,
+}
+
+impl Request {
+    /// Whether a failed attempt may be replayed without side effects.
+    pub fn is_idempotent(&self) -> bool {
+        matches!(self.method, Method::GET | Method::HEAD | Method::OPTIONS)
+    }
+}
+
+/// The parts of an HTTP response the scrapers look at.
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub status: u16,
+    pub body: String,
+}
+
+/// Sends requests on behalf of a `Client`.
+///
+/// Implemented for `reqwest::blocking::Client`; tests swap in a transport that replays fixtures.
+pub trait Transport: Send + Sync {
+    fn send(&self, request: Request) -> Result<Response>;
+}
+
+impl Transport for reqwest::blocking::Client {
+    fn send(&self, request: Request) -> Result<Response> {
+        let mut req = self.request(request.method, &request.url)
+            .headers(request.headers);
+        if let Some(timeout) = request.timeout {
+            req = req.timeout(timeout);
+        }
+        if let Some(body) = &request.body {
+            req = req.json(body);
+        }
+
+        let res = req.send()?;
+
+        Ok(Response {
+            status: res.status().as_u16(),
+            body: res.text()?,
+        })
+    }
+}
+
+
+/// HTTP client shared by every scraper of the typed API.
+#[derive(Clone)]
+pub struct Client {
+    transport: Arc<dyn Transport>,
+    settings: Settings,
+    deadline: Option<Instant>,
+    cancel_token: Option<CancelToken>,
+    cache: Arc<Cache>,
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Self::from_settings(config::settings()).expect("Failed to build the HTTP client")
+    }
+}
+
+impl fmt::Debug for Client {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Client")
+            .field("settings", &self.settings)
+            .finish_non_exhaustive()
+    }
+}
+
+static SHARED: LazyLock<Mutex<Option<Client>>> = LazyLock::new(|| Mutex::new(None));
+
+impl Client {
+    /// Build a client with its own connection pool from the settings currently in effect.
+    ///
+    /// Panics if the TLS backend cannot be initialised, use `from_settings` to handle that.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The process-wide client used by the FFI entry points.
+    ///
+    /// Built lazily and rebuilt whenever `set_config` changes the settings, so connections are pooled across calls.
+    pub fn shared() -> Result<Client> {
+        let settings = config::settings();
+        let mut shared = SHARED.lock().unwrap_or_else(|e| e.into_inner());
+
+        if let Some(client) = shared.as_ref()
+            && client.settings == settings {
+            return Ok(client.clone());
+        }
+
+        let mut client = Self::from_settings(settings)?;
+
+        // Responses are cached per host and keys per moviesapi host, so the cache outlives a settings change.
+        if let Some(previous) = shared.as_ref() {
+            client.cache = previous.cache.clone();
+        }
+
+        *shared = Some(client.clone());
+
+        Ok(client)
+    }
+
+    /// Build a pooled reqwest client configured by `settings`.
+    pub fn from_settings(settings: Settings) -> Result<Self> {
+        let mut builder = reqwest::blocking::Client::builder()
+            .connect_timeout(Duration::from_millis(settings.connect_timeout_ms))
+            .pool_idle_timeout(Duration::from_millis(settings.pool_idle_timeout_ms))
+            .pool_max_idle_per_host(settings.pool_max_idle_per_host);
+
+        if settings.tcp_keepalive_ms > 0 {
+            builder = builder.tcp_keepalive(Duration::from_millis(settings.tcp_keepalive_ms));
+        }
+
+        let http = builder.build()
+            .map_err(|e| Error::Internal(format!("Failed to build the HTTP client: {}", e)))?;
+
+        Ok(Self::with_transport(http).with_settings(settings))
+    }
+
+    pub fn from_reqwest(http: reqwest::blocking::Client) -> Self {
+        Self::with_transport(http)
+    }
+
+    /// Build a client on top of `transport`, using the settings currently in effect.
+    pub fn with_transport<T: Transport + 'static>(transport: T) -> Self {
+        Client {
+            transport: Arc::new(transport),
+            settings: config::settings(),
+            deadline: None,
+            cancel_token: None,
+            cache: Arc::new(Cache::new()),
+        }
+    }
+
+    /// Bound every request made through this handle, retries included, to finish within `budget`.
+    ///
+    /// Never extends a deadline the handle already has: the earlier of the two is kept.
+    pub fn with_deadline(mut self, budget: Duration) -> Self {
+        let deadline = Instant::now() + budget;
+        self.deadline = Some(self.deadline.map_or(deadline, |current| current.min(deadline)));
+        self
+    }
+
+    /// Stop every request made through this handle with `Error::Cancelled` once `token` is cancelled.
+    ///
+    /// Requests in flight are abandoned right away instead of being waited for. They run to their own timeout in the background.
+    pub fn with_cancel_token(mut self, token: CancelToken) -> Self {
+        self.cancel_token = Some(token);
+        self
+    }
+
+    /// Replace the settings this client was created with.
+    ///
+    /// Only hosts and headers follow; the connection pool keeps the timeouts it was built with.
+    pub fn with_settings(mut self, settings: Settings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// Cache shared by every clone of this client.
+    pub fn cache(&self) -> &Cache {
+        &self.cache
+    }
+
+    /// GET `url` and return the body of a successful response.
+    pub(crate) fn get(&self, url: &str, headers: HeaderMap) -> Result<String> {
+        self.send(Request {
+            method: Method::GET,
+            url: url.to_string(),
+            headers,
+            body: None,
+            timeout: None,
+        })
+    }
+
+    /// POST `url`, optionally with a JSON body, and return the body of a successful response.
+    pub(crate) fn post(&self, url: &str, headers: HeaderMap, body: Option<&Value>) -> Result<String> {
+        self.send(Request {
+            method: Method::POST,
+            url: url.to_string(),
+            headers,
+            body: body.cloned(),
+            timeout: None,
+        })
+    }
+
+    /// Headers sent with every request, before the per-request ones.
+    fn default_headers(&self) -> Result<HeaderMap> {
+        let mut headers = HeaderMap::new();
+
+        headers.insert(USER_AGENT, header_value(&self.settings.user_agent)?);
+        headers.insert(ACCEPT, HeaderValue::from_static("*/*"));
+
+        Ok(headers)
+    }
+
+    fn send(&self, mut request: Request) -> Result<String> {
+        let mut headers = self.default_headers()?;
+        headers.extend(request.headers);
+        request.headers = headers;
+
+        let mut attempt = 0;
+        loop {
+            self.check_cancelled()?;
+            request.timeout = Some(attempt_timeout(&self.settings, self.deadline, &request.url)?);
+
+            let res = self.transport_send(request.clone());
+
+            // Do not hand a response to the scraper once the call has been cancelled.
+            self.check_cancelled()?;
+
+            let error = match res {
+                Ok(res) if (200..300).contains(&res.status) => return Ok(res.body),
+                Ok(res) => Error::UpstreamHttp { status: res.status, url: request.url.clone() },
+                Err(e) => e,
+            };
+
+            if !is_transient(&request, &error) {
+                return Err(error);
+            }
+
+            if attempt >= self.settings.max_retries {
+                return Err(match attempt {
+                    0 => error,
+                    _ => Error::RetriesExhausted { attempts: attempt + 1, last: Box::new(error) },
+                });
+            }
+
+            let delay = self.backoff(attempt);
+            if self.deadline.is_some_and(|deadline| Instant::now() + delay >= deadline) {
+                return Err(Error::Timeout(format!("Deadline reached while retrying {}: {}", request.url, error)));
+            }
+
+            self.sleep(delay)?;
+            attempt += 1;
+        }
+    }
+
+    /// Whether the call was cancelled or ran past its deadline, so that no request through this handle can succeed.
+    pub(crate) fn is_stopped(&self) -> bool {
+        self.cancel_token.as_ref().is_some_and(|token| token.is_cancelled())
+            || self.deadline.is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    fn check_cancelled(&self) -> Result<()> {
+        match &self.cancel_token {
+            Some(token) if token.is_cancelled() => Err(Error::Cancelled),
+            _ => Ok(()),
+        }
+    }
+
+    /// Send on the transport. With a cancel token the request runs on a request thread, so it can be abandoned mid-flight.
+    ///
+    /// The call stops waiting once cancelled or past its deadline, however long the request queued for a thread.
+    /// An abandoned request is not aborted: it holds its thread and connection until it completes or times out,
+    /// which is never later than the deadline, as its timeout is counted from when a thread picks it up.
+    fn transport_send(&self, request: Request) -> Result<Response> {
+        let Some(token) = &self.cancel_token else {
+            return self.transport.send(request);
+        };
+
+        let (sender, receiver) = mpsc::channel();
+        let transport = self.transport.clone();
+        let settings = self.settings.clone();
+        let deadline = self.deadline;
+        let url = request.url.clone();
+        runtime::spawn_request(Box::new(move || {
+            // Waiting for this thread used up part of the deadline, which bounds the request from now on.
+            let res = attempt_timeout(&settings, deadline, &request.url)
+                .and_then(|timeout| transport.send(Request { timeout: Some(timeout), ..request }));
+
+            // The receiver is gone if the call was cancelled meanwhile; the response is simply dropped.
+            let _ = sender.send(res);
+        }))?;
+
+        loop {
+            match receiver.recv_timeout(CANCEL_POLL_INTERVAL) {
+                Ok(res) => return res,
+                Err(RecvTimeoutError::Timeout) if token.is_cancelled() => return Err(Error::Cancelled),
+                Err(RecvTimeoutError::Timeout) if deadline.is_some_and(|deadline| Instant::now() >= deadline) => {
+                    return Err(Error::Timeout(format!("Deadline reached while waiting for {}", url)));
+                },
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => return Err(Error::Internal(String::from("The request thread stopped unexpectedly."))),
+            }
+        }
+    }
+
+    /// Wait `delay` between retries, waking up early when cancelled.
+    fn sleep(&self, delay: Duration) -> Result<()> {
+        let Some(token) = &self.cancel_token else {
+            thread::sleep(delay);
+            return Ok(());
+        };
+
+        let until = Instant::now() + delay;
+        while let Some(left) = until.checked_duration_since(Instant::now()).filter(|left| !left.is_zero()) {
+            if token.is_cancelled() {
+                return Err(Error::Cancelled);
+            }
+            thread::sleep(left.min(CANCEL_POLL_INTERVAL));
+        }
+
+        self.check_cancelled()
+    }
+
+    /// Exponential backoff with jitter: a random delay between half and all of `base * 2^attempt`.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let max_delay = self.settings.retry_max_delay_ms;
+        let delay = self.settings.retry_base_delay_ms
+            .saturating_mul(1u64 << attempt.min(16))
+            .min(max_delay);
+
+        Duration::from_millis(rand::rng().random_range(delay / 2..=delay))
+    }
+}
+
+/// The per-request timeout, shortened to what is left of the deadline.
+fn attempt_timeout(settings: &Settings, deadline: Option<Instant>, url: &str) -> Result<Duration> {
+    let timeout = Duration::from_millis(settings.request_timeout_ms);
+
+    match deadline {
+        Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+            Some(left) if !left.is_zero() => Ok(timeout.min(left)),
+            _ => Err(Error::Timeout(format!("Deadline reached before requesting {}", url))),
+        },
+        None => Ok(timeout),
+    }
+}
+
+/// Whether `error` may go away on its own if `request` is sent again.
+fn is_transient(request: &Request, error: &Error) -> bool {
+    match error {
+        // Nothing reached a handler, so even a non idempotent request is safe to replay.
+        Error::UpstreamHttp { status: 429 | 502 | 503 | 504, .. } => true,
+        Error::UpstreamHttp { status, .. } => *status >= 500 && request.is_idempotent(),
+        Error::Network(_) | Error::Timeout(_) => request.is_idempotent(),
+        _ => false,
+    }
+}
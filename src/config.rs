@@ -0,0 +1,145 @@
+use std::sync::{LazyLock, RwLock};
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{
+    DEFAULT_USER_AGENT, PROXY_HOST, SCRAPIFY_URL,
+    SERVER_HOST, SERVER_ORIGIN, SERVER_REFERER,
+    SOURCE_HOST, SOURCE_ORIGIN, SOURCE_REFERER,
+};
+
+
+/// Runtime overridable library settings. Every field falls back to its default when omitted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Base url of simkl, used by `search` and `get_episode_list`.
+    pub source_base_url: String,
+    pub source_referer: String,
+    pub source_origin: String,
+    /// Base url the search posters are served from.
+    pub poster_base_url: String,
+
+    /// Base url of moviesapi, used by `get_server`.
+    pub server_base_url: String,
+    pub server_referer: String,
+    pub server_origin: String,
+    pub scrapify_url: String,
+    /// Host the resolved stream is proxied through. Empty to return the upstream url untouched.
+    pub proxy_host: String,
+
+    /// `User-Agent` sent with every request.
+    pub user_agent: String,
+    pub connect_timeout_ms: u64,
+    /// How long an idle pooled connection is kept open.
+    pub pool_idle_timeout_ms: u64,
+    pub pool_max_idle_per_host: usize,
+    /// TCP keep-alive interval. `0` disables it.
+    pub tcp_keepalive_ms: u64,
+
+    /// Deadline of a single request, including reading the body.
+    pub request_timeout_ms: u64,
+    /// Deadline of a whole entry point call, across every request and retry it makes.
+    pub deadline_ms: u64,
+    /// Retries of a transient failure on top of the first attempt.
+    pub max_retries: u32,
+    /// First backoff delay, doubled on every retry and jittered.
+    pub retry_base_delay_ms: u64,
+    pub retry_max_delay_ms: u64,
+    /// Deadline of every server resolved by `get_episode_server` with `probe`. They are resolved at once.
+    pub probe_timeout_ms: u64,
+
+    /// How long keys extracted from moviesapi are reused. `0` disables the keys cache.
+    pub keys_ttl_ms: u64,
+    /// JSON file the keys are persisted to, so restarts start warm.
+    pub keys_cache_path: Option<String>,
+
+    /// Number of `search` and `get_episode_list` results kept in memory. `0` disables the response cache.
+    pub cache_capacity: usize,
+    /// Directory the cached responses are also written to, so restarts start warm.
+    pub cache_dir: Option<String>,
+    /// How long a `search` result is reused. `0` disables caching it.
+    pub search_cache_ttl_ms: u64,
+    /// How long a `get_episode_list` result is reused. `0` disables caching it.
+    pub episode_list_cache_ttl_ms: u64,
+    /// How long a simkl title page, shared by `get_details` and `get_episode_list`, is reused. `0` disables caching it.
+    pub title_page_cache_ttl_ms: u64,
+
+    /// Size of the worker pool running the `*_async` exports. Read when the first async call starts.
+    pub async_workers: usize,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            source_base_url: format!("https://{}", SOURCE_HOST),
+            source_referer: SOURCE_REFERER.to_string(),
+            source_origin: SOURCE_ORIGIN.to_string(),
+            poster_base_url: String::from("https://simkl.in/posters"),
+
+            server_base_url: format!("https://{}", SERVER_HOST),
+            server_referer: SERVER_REFERER.to_string(),
+            server_origin: SERVER_ORIGIN.to_string(),
+            scrapify_url: SCRAPIFY_URL.to_string(),
+            proxy_host: PROXY_HOST.to_string(),
+
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            connect_timeout_ms: 10_000,
+            pool_idle_timeout_ms: 90_000,
+            pool_max_idle_per_host: 8,
+            tcp_keepalive_ms: 60_000,
+
+            request_timeout_ms: 15_000,
+            deadline_ms: 45_000,
+            max_retries: 2,
+            retry_base_delay_ms: 300,
+            retry_max_delay_ms: 3_000,
+            probe_timeout_ms: 10_000,
+
+            keys_ttl_ms: 6 * 60 * 60 * 1000,
+            keys_cache_path: None,
+
+            cache_capacity: 128,
+            cache_dir: None,
+            search_cache_ttl_ms: 10 * 60 * 1000,
+            episode_list_cache_ttl_ms: 60 * 60 * 1000,
+            title_page_cache_ttl_ms: 60 * 60 * 1000,
+
+            async_workers: 4,
+        }
+    }
+}
+
+impl Settings {
+    /// `Host` header value for requests to `source_base_url`.
+    pub fn source_host(&self) -> String {
+        host_of(&self.source_base_url)
+    }
+
+    /// `Host` header value for requests to `server_base_url`.
+    pub fn server_host(&self) -> String {
+        host_of(&self.server_base_url)
+    }
+}
+
+fn host_of(base_url: &str) -> String {
+    Url::parse(base_url).ok()
+        .and_then(|url| url.host_str().map(|host| match url.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        }))
+        .unwrap_or_default()
+}
+
+
+static SETTINGS: LazyLock<RwLock<Settings>> = LazyLock::new(|| RwLock::new(Settings::default()));
+
+/// Snapshot of the settings currently in effect.
+pub fn settings() -> Settings {
+    SETTINGS.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+pub fn set_settings(settings: Settings) {
+    *SETTINGS.write().unwrap_or_else(|e| e.into_inner()) = settings;
+}
@@ -0,0 +1,187 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+
+/// Machine readable error code returned inside the `error` object of every envelope.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    InvalidArguments,
+    Network,
+    Timeout,
+    UpstreamHttp,
+    RetriesExhausted,
+    ParseFailed,
+    LinkIdNotFound,
+    KeysNotFound,
+    Encryption,
+    Cancelled,
+    NoServerPlayed,
+    Internal,
+}
+
+impl ErrorCode {
+    /// The code as it appears in an envelope.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::InvalidArguments => "INVALID_ARGUMENTS",
+            ErrorCode::Network => "NETWORK",
+            ErrorCode::Timeout => "TIMEOUT",
+            ErrorCode::UpstreamHttp => "UPSTREAM_HTTP",
+            ErrorCode::RetriesExhausted => "RETRIES_EXHAUSTED",
+            ErrorCode::ParseFailed => "PARSE_FAILED",
+            ErrorCode::LinkIdNotFound => "LINK_ID_NOT_FOUND",
+            ErrorCode::KeysNotFound => "KEYS_NOT_FOUND",
+            ErrorCode::Encryption => "ENCRYPTION",
+            ErrorCode::Cancelled => "CANCELLED",
+            ErrorCode::NoServerPlayed => "NO_SERVER_PLAYED",
+            ErrorCode::Internal => "INTERNAL",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// The arguments passed by the host are missing or malformed.
+    InvalidArguments(String),
+    /// The request never produced a response (DNS, TLS, connection reset...).
+    Network(String),
+    /// A request or the whole call ran past its deadline.
+    Timeout(String),
+    /// The upstream answered with a non-success HTTP status.
+    UpstreamHttp { status: u16, url: String },
+    /// A transient failure persisted through every retry.
+    RetriesExhausted { attempts: u32, last: Box<Error> },
+    /// The upstream answered, but not with what we expected.
+    ParseFailed(String),
+    /// An external id the stream provider needs (e.g. TMDB) is not linked from the title page.
+    LinkIdNotFound(String),
+    /// `SCRAPIFY_URL`, `ENCRYPTION_KEY` or `PLAYER_API_KEY` could not be extracted.
+    KeysNotFound(String),
+    Encryption(String),
+    /// The host cancelled the call through its request handle.
+    Cancelled,
+    /// `get_server` fell back through every server and none played.
+    NoServerPlayed(Vec<FailedServer>),
+    /// The request for one server failed (no stream, a timeout, an error status...) in a way the other servers
+    /// need not. Set by providers for `get_server` to fall back on, and reported as the error it wraps.
+    ServerFailed(Box<Error>),
+    Internal(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Error::InvalidArguments(_) => ErrorCode::InvalidArguments,
+            Error::Network(_) => ErrorCode::Network,
+            Error::Timeout(_) => ErrorCode::Timeout,
+            Error::UpstreamHttp { .. } => ErrorCode::UpstreamHttp,
+            Error::RetriesExhausted { .. } => ErrorCode::RetriesExhausted,
+            Error::ParseFailed(_) => ErrorCode::ParseFailed,
+            Error::LinkIdNotFound(_) => ErrorCode::LinkIdNotFound,
+            Error::KeysNotFound(_) => ErrorCode::KeysNotFound,
+            Error::Encryption(_) => ErrorCode::Encryption,
+            Error::Cancelled => ErrorCode::Cancelled,
+            Error::NoServerPlayed(_) => ErrorCode::NoServerPlayed,
+            Error::ServerFailed(e) => e.code(),
+            Error::Internal(_) => ErrorCode::Internal,
+        }
+    }
+
+    pub fn http_status(&self) -> Option<u16> {
+        match self {
+            Error::UpstreamHttp { status, .. } => Some(*status),
+            Error::RetriesExhausted { last, .. } => last.http_status(),
+            Error::ServerFailed(e) => e.http_status(),
+            _ => None,
+        }
+    }
+
+    pub fn attempts(&self) -> Option<u32> {
+        match self {
+            Error::RetriesExhausted { attempts, .. } => Some(*attempts),
+            Error::ServerFailed(e) => e.attempts(),
+            _ => None,
+        }
+    }
+
+    /// The error itself, without the `ServerFailed` around it.
+    pub fn into_cause(self) -> Error {
+        match self {
+            Error::ServerFailed(e) => e.into_cause(),
+            e => e,
+        }
+    }
+
+    pub fn info(&self) -> ErrorInfo {
+        ErrorInfo {
+            code: self.code(),
+            http_status: self.http_status(),
+            attempts: self.attempts(),
+            message: self.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidArguments(msg) => write!(f, "Invalid arguments: {}", msg),
+            Error::Network(msg) => write!(f, "Network error: {}", msg),
+            Error::Timeout(msg) => write!(f, "Timed out: {}", msg),
+            Error::UpstreamHttp { status, url } => write!(f, "Upstream returned HTTP {} for {}", status, url),
+            Error::RetriesExhausted { attempts, last } => write!(f, "Gave up after {} attempts: {}", attempts, last),
+            Error::ParseFailed(msg) => write!(f, "Failed to parse upstream response: {}", msg),
+            Error::LinkIdNotFound(msg) => write!(f, "Link ID not found: {}", msg),
+            Error::KeysNotFound(msg) => write!(f, "Keys not found: {}", msg),
+            Error::Encryption(msg) => write!(f, "Encryption failed: {}", msg),
+            Error::Cancelled => write!(f, "Cancelled"),
+            Error::NoServerPlayed(failed) => {
+                write!(f, "No server played:")?;
+                for (i, server) in failed.iter().enumerate() {
+                    let separator = if i == 0 { " " } else { ", " };
+                    write!(f, "{}{}/{} srv {} ({})", separator, server.provider, server.source_id, server.srv, server.error.code.as_str())?;
+                }
+                Ok(())
+            },
+            Error::ServerFailed(e) => write!(f, "{}", e),
+            Error::Internal(msg) => write!(f, "Internal error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        match (e.status(), e.url()) {
+            (Some(status), Some(url)) => Error::UpstreamHttp { status: status.as_u16(), url: url.to_string() },
+            _ if e.is_timeout() => Error::Timeout(e.to_string()),
+            _ if e.is_decode() => Error::ParseFailed(e.to_string()),
+            _ => Error::Network(e.to_string()),
+        }
+    }
+}
+
+
+/// The `error` object of a failed envelope.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorInfo {
+    pub code: ErrorCode,
+    pub http_status: Option<u16>,
+    /// Number of attempts made when retries were exhausted.
+    pub attempts: Option<u32>,
+    pub message: String,
+}
+
+/// A server `get_server` tried in `fallback` mode, and why it did not play.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailedServer {
+    pub provider: String,
+    pub source_id: String,
+    pub srv: usize,
+    pub error: ErrorInfo,
+}
@@ -1,6 +1,7 @@
 use std::ffi::{c_char, CString};
 
 #[unsafe(no_mangle)]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 pub extern "C" fn free_ptr(ptr: *mut c_char) {
     if ptr.is_null() { return; }
     unsafe {
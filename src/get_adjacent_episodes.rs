@@ -0,0 +1,117 @@
+use std::ffi::c_void;
+use std::os::raw::{c_char};
+
+use serde::{Deserialize, Serialize};
+use urlencoding::{encode};
+
+use crate::cache::Endpoint;
+use crate::client::Client;
+use crate::error::{Error, Result};
+use crate::get_episode_list::{episode_list, EpisodeData, EpisodeList};
+use crate::get_server::EpisodeInfo;
+use crate::utils::ffi;
+
+
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdjacentEpisodes {
+    pub previous: Option<EpisodeData>,
+    pub next: Option<EpisodeData>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ReturnResult {
+    status: bool,
+    message: String,
+    data: AdjacentEpisodes,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Arguments {
+    episode_id: String,
+}
+
+/// Find the episodes before and after `episode_id`, rolling over into the neighbouring seasons.
+#[unsafe(no_mangle)]
+pub extern "C" fn get_adjacent_episodes(
+    arguments_ptr : *const c_char,
+) -> *const c_char {
+    ffi::call("Get Adjacent Episodes", arguments_ptr, run)
+}
+
+/// Non-blocking `get_adjacent_episodes`: returns a request handle and hands the envelope to `callback` from a worker thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn get_adjacent_episodes_async(
+    arguments_ptr : *const c_char,
+    callback: Option<ffi::Callback>,
+    user_data: *mut c_void,
+) -> u64 {
+    ffi::call_async("Get Adjacent Episodes", arguments_ptr, callback, user_data, run)
+}
+
+fn run(args: Arguments, client: &Client) -> Result<ReturnResult> {
+    let data = adjacent_episodes(client, &args.episode_id)?;
+
+    Ok(ReturnResult {
+        status: true,
+        message: String::from("Success"),
+        data,
+    })
+}
+
+
+/// The episodes before and after `episode_id`, as returned by `get_episode_list` or `get_episode_server`.
+///
+/// Reads the episode list through the response cache, so this costs no request right after `get_episode_list`.
+/// Specials only lead to other specials, and regular seasons skip them.
+pub fn adjacent_episodes(client: &Client, episode_id: &str) -> Result<AdjacentEpisodes> {
+    let episode_info = EpisodeInfo::decode(episode_id)?;
+
+    let Some(numbers) = episode_info.numbers() else {
+        // A movie has no neighbours.
+        return Ok(AdjacentEpisodes { previous: None, next: None });
+    };
+
+    let title_id = title_id(&episode_info)
+        .ok_or_else(|| Error::InvalidArguments(String::from("The episode id does not name its simkl title.")))?;
+
+    let episode_list = client.cache().get_or_fetch(client.settings(), Endpoint::EpisodeList, &title_id, false, || {
+        episode_list(client, &encode(&title_id))
+    })?;
+
+    let EpisodeList::Series { seasons } = episode_list else {
+        return Ok(AdjacentEpisodes { previous: None, next: None });
+    };
+
+    let is_special = numbers.0 == 0;
+    let episodes = seasons.into_iter()
+        .flat_map(|season| season.episodes)
+        .filter_map(|episode| {
+            let numbers = EpisodeInfo::decode(&episode.id).ok()?.numbers()?;
+            ((numbers.0 == 0) == is_special).then_some((numbers, episode))
+        })
+        .collect::<Vec<_>>();
+
+    let position = episodes.iter()
+        .position(|(episode_numbers, _)| *episode_numbers == numbers)
+        .ok_or_else(|| Error::InvalidArguments(format!("Season {} episode {} is not listed on simkl.", numbers.0, numbers.1)))?;
+
+    let neighbour = |position: Option<usize>| position
+        .and_then(|position| episodes.get(position))
+        .map(|(_, episode)| episode.clone());
+
+    Ok(AdjacentEpisodes {
+        previous: neighbour(position.checked_sub(1)),
+        next: neighbour(Some(position + 1)),
+    })
+}
+
+/// Simkl path of the title, or `/tv/{simkl id}` for ids handed out before it was included.
+fn title_id(episode_info: &EpisodeInfo) -> Option<String> {
+    episode_info.title_id.clone().or_else(|| {
+        let simkl_id = episode_info.ids.simkl_id.as_deref()?;
+
+        Some(format!("/{}/{}", episode_info.id_type, simkl_id))
+    })
+}
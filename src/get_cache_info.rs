@@ -0,0 +1,44 @@
+use std::os::raw::{c_char};
+
+use serde::{Deserialize, Serialize};
+
+use crate::cache::CacheEntryInfo;
+use crate::client::Client;
+use crate::utils::ffi;
+
+
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheInfo {
+    capacity: usize,
+    cache_dir: Option<String>,
+    /// Most recently used first.
+    entries: Vec<CacheEntryInfo>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ReturnResult {
+    status: bool,
+    message: String,
+    data: CacheInfo,
+}
+
+
+/// List the responses currently held in memory, with their age and remaining lifetime.
+#[unsafe(no_mangle)]
+pub extern "C" fn get_cache_info() -> *const c_char {
+    ffi::respond("Get Cache Info", || {
+        let client = Client::shared()?;
+        let settings = client.settings();
+
+        Ok(ReturnResult {
+            status: true,
+            message: String::from("Success"),
+            data: CacheInfo {
+                capacity: settings.cache_capacity,
+                cache_dir: settings.cache_dir.clone(),
+                entries: client.cache().entries(settings),
+            },
+        })
+    })
+}
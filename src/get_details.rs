@@ -0,0 +1,222 @@
+use std::ffi::c_void;
+use std::os::raw::{c_char};
+
+use serde::{Deserialize, Serialize};
+use visdom::Vis;
+use visdom::types::Elements;
+use urlencoding::{decode};
+use html_escape::decode_html_entities;
+
+use crate::client::Client;
+use crate::error::{Error, Result};
+use crate::get_episode_list::title_page;
+use crate::utils::ffi;
+
+
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CastMember {
+    pub name: String,
+    pub character: Option<String>,
+    pub image: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Ratings {
+    /// Out of 10.
+    pub simkl: Option<f64>,
+    pub simkl_votes: Option<u64>,
+    /// Out of 10.
+    pub imdb: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Details {
+    pub title: String,
+    pub synopsis: Option<String>,
+    pub genres: Vec<String>,
+    pub cast: Vec<CastMember>,
+    /// Minutes per episode, or of the whole movie.
+    pub runtime: Option<u32>,
+    pub status: Option<String>,
+    pub network: Option<String>,
+    pub ratings: Ratings,
+    pub backdrop: Option<String>,
+    pub trailer: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ReturnResult {
+    status: bool,
+    message: String,
+    data: Details,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Arguments {
+    id: String,
+    /// Skip the response cache and fetch the title page again.
+    #[serde(default)]
+    bypass_cache: bool,
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn get_details(
+    arguments_ptr : *const c_char,
+) -> *const c_char {
+    ffi::call("Get Details", arguments_ptr, run)
+}
+
+/// Non-blocking `get_details`: returns a request handle and hands the envelope to `callback` from a worker thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn get_details_async(
+    arguments_ptr : *const c_char,
+    callback: Option<ffi::Callback>,
+    user_data: *mut c_void,
+) -> u64 {
+    ffi::call_async("Get Details", arguments_ptr, callback, user_data, run)
+}
+
+fn run(args: Arguments, client: &Client) -> Result<ReturnResult> {
+    let format_id = decode(&args.id)
+        .map_err(|e| Error::InvalidArguments(e.to_string()))?;
+
+    let html = title_page(client, &format_id, args.bypass_cache)?;
+
+    Ok(ReturnResult {
+        status: true,
+        message: String::from("Success"),
+        data: parse_details(&html)?,
+    })
+}
+
+
+/// Scrape the metadata of the simkl title `id`, as returned by `search`.
+pub fn title_details(client: &Client, id: &str) -> Result<Details> {
+    let format_id = decode(id)
+        .map_err(|e| Error::InvalidArguments(e.to_string()))?;
+
+    parse_details(&title_page(client, &format_id, false)?)
+}
+
+/// Read the metadata out of a simkl title page. Only the title is required.
+pub fn parse_details(html: &str) -> Result<Details> {
+    let vis = Vis::load(html).map_err(|e| Error::ParseFailed(e.to_string()))?;
+
+    let title = text_of(&vis.find("h1[itemprop=name]"))
+        .ok_or_else(|| Error::ParseFailed(String::from("Title page has no title.")))?;
+
+    let synopsis = text_of(&vis.find("[itemprop=description]"))
+        .or_else(|| attr_of(&vis.find("meta[property='og:description']"), "content"));
+
+    let genres = vis.find("[itemprop=genre]")
+        .into_iter()
+        .filter_map(|genre| text_of(&Vis::dom(&genre)))
+        .collect::<Vec<_>>();
+
+    let mut cast: Vec<CastMember> = Vec::new();
+    for actor in vis.find("[itemprop=actor]") {
+        let actor_ele = Vis::dom(&actor);
+        let Some(name) = text_of(&actor_ele.find("[itemprop=name]")) else {
+            continue;
+        };
+
+        cast.push(CastMember {
+            name,
+            character: text_of(&actor_ele.find(".SimklTVAboutCastCharacter")),
+            image: attr_of(&actor_ele.find("img"), "src"),
+        });
+    }
+
+
+    /* Details table */
+
+    let mut status = None;
+    let mut network = None;
+
+    for row in vis.find(".SimklTVAboutTabsDetails tr") {
+        let row_ele = Vis::dom(&row);
+        let Some(label) = text_of(&row_ele.find(".SimklTVAboutTabsDetailsLabel")) else {
+            continue;
+        };
+        let value = text_of(&row_ele.find(".SimklTVAboutTabsDetailsValue"));
+
+        match label.trim_end_matches(':').to_lowercase().as_str() {
+            "status" => status = value,
+            "network" => network = value,
+            _ => {},
+        }
+    }
+
+    // ISO 8601 duration (`PT50M`) in `content`, else the displayed `50 min`.
+    let runtime_ele = vis.find("[itemprop=timeRequired]");
+    let runtime = attr_of(&runtime_ele, "content")
+        .or_else(|| text_of(&runtime_ele))
+        .and_then(|runtime| parse_minutes(&runtime));
+
+    /* --- */
+
+
+    let ratings = Ratings {
+        simkl: text_of(&vis.find("[itemprop=aggregateRating] [itemprop=ratingValue]"))
+            .and_then(|rating| rating.parse().ok()),
+        simkl_votes: attr_of(&vis.find("[itemprop=aggregateRating] [itemprop=ratingCount]"), "content")
+            .and_then(|votes| votes.parse().ok()),
+        imdb: text_of(&vis.find(".SimklTVRatingImdb"))
+            .and_then(|rating| rating.parse().ok()),
+    };
+
+    let backdrop = attr_of(&vis.find("meta[property='og:image']"), "content");
+    let trailer = attr_of(&vis.find("[itemprop=trailer] [itemprop=embedUrl]"), "content");
+
+    Ok(Details {
+        title,
+        synopsis,
+        genres,
+        cast,
+        runtime,
+        status,
+        network,
+        ratings,
+        backdrop,
+        trailer,
+    })
+}
+
+/// Trimmed, entity decoded text of the first element, if it has any.
+fn text_of(ele: &Elements) -> Option<String> {
+    if ele.length() == 0 {
+        return None;
+    }
+
+    let text = ele.first().text();
+    let text = decode_html_entities(text.split_whitespace().collect::<Vec<_>>().join(" ").as_str()).to_string();
+
+    (!text.is_empty()).then_some(text)
+}
+
+fn attr_of(ele: &Elements, name: &str) -> Option<String> {
+    ele.first().attr(name)
+        .map(|value| decode_html_entities(&value.to_string()).trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// Leading number of `PT1H5M`, `50 min` or `50`, in minutes.
+fn parse_minutes(runtime: &str) -> Option<u32> {
+    let runtime = runtime.trim();
+
+    if let Some(duration) = runtime.strip_prefix("PT") {
+        let hours = duration.split_once('H').map(|(hours, _)| hours.parse::<u32>().ok()).unwrap_or(Some(0))?;
+        let minutes = duration.rsplit('H').next()
+            .and_then(|rest| rest.strip_suffix('M'))
+            .map(|minutes| minutes.parse::<u32>().ok())
+            .unwrap_or(Some(0))?;
+
+        return Some(hours * 60 + minutes).filter(|minutes| *minutes > 0);
+    }
+
+    let digits = runtime.chars().take_while(|c| c.is_ascii_digit()).collect::<String>();
+
+    digits.parse().ok().filter(|minutes| *minutes > 0)
+}
@@ -1,243 +1,620 @@
-use std::ffi::{CString, CStr};
+use std::collections::BTreeMap;
+use std::ffi::c_void;
+use std::time::{SystemTime, UNIX_EPOCH};
 use std::os::raw::{c_char};
-use std::panic;
-use std::ptr;
 
 use serde::{Deserialize, Serialize};
-use serde_json::{from_str, to_string, json};
-use reqwest::header::{HeaderMap, USER_AGENT, HeaderValue};
+use serde_json::{to_string, json};
+use reqwest::header::{HeaderMap};
 use visdom::Vis;
+use url::Url;
 use urlencoding::{encode, decode};
 use html_escape::decode_html_entities;
 
-use crate::{ SOURCE_HOST };
-
-
-
+use crate::cache::Endpoint;
+use crate::client::Client;
+use crate::provider::require_playable;
+use crate::error::{Error, Result};
+use crate::utils::ffi;
+
+
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EpisodeData{
+    pub index: usize,
+    pub id: String,
+    pub title: String,
+    /// Season number as listed by simkl, `0` for specials, the same the episode id and the `season` filter use.
+    pub season: Option<usize>,
+    /// Episode number as shown by simkl.
+    pub number: Option<usize>,
+    /// `YYYY-MM-DD`.
+    pub air_date: Option<String>,
+    pub image: Option<String>,
+    pub overview: Option<String>,
+    /// `false` once the air date is known to be in the future.
+    pub aired: bool
+}
 
-#[derive(Debug, Serialize, Deserialize)]
-struct EpisodeData{
-    index: usize,
-    id: String,
-    title: String
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Season {
+    /// As listed by simkl, `0` for specials. A heading without a number counts as the season's position, from `1`.
+    pub number: Option<usize>,
+    /// The heading, e.g. `Season 1` or `Specials`.
+    pub title: String,
+    pub episode_count: usize,
+    pub poster: Option<String>,
+    pub episodes: Vec<EpisodeData>,
 }
 
+/// Response shape of `get_episode_list`, picked with the `version` argument.
 #[derive(Debug, Serialize, Deserialize)]
-struct ReturnResult {
-    status: bool,
-    message: String,
-    data: Vec<Vec<Vec<EpisodeData>>>,
+#[serde(untagged)]
+enum ReturnResult {
+    /// `data[season][0][episode]`, with movies as a single `Full` episode.
+    V1 {
+        status: bool,
+        message: String,
+        data: Vec<Vec<Vec<EpisodeData>>>,
+    },
+    V2 {
+        status: bool,
+        message: String,
+        version: u32,
+        data: EpisodeList,
+    },
 }
 
 #[derive(Serialize, Deserialize)]
 struct Arguments {
-    id: String
+    id: String,
+    /// Skip the response cache and scrape the episodes again.
+    #[serde(default)]
+    bypass_cache: bool,
+    #[serde(default = "first_version")]
+    version: u32,
+    #[serde(flatten)]
+    filter: EpisodeFilter,
+}
+
+fn first_version() -> u32 {
+    1
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn get_episode_list(
     arguments_ptr : *const c_char,
 ) -> *const c_char {
-    let result = panic::catch_unwind(|| {
+    ffi::call("Get Episode List", arguments_ptr, run)
+}
 
-        let mut return_result = ReturnResult {
-            status: false,
-            message: String::from(""),
-            data: Vec::new(),
-        };
+/// Non-blocking `get_episode_list`: returns a request handle and hands the envelope to `callback` from a worker thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn get_episode_list_async(
+    arguments_ptr : *const c_char,
+    callback: Option<ffi::Callback>,
+    user_data: *mut c_void,
+) -> u64 {
+    ffi::call_async("Get Episode List", arguments_ptr, callback, user_data, run)
+}
+
+fn run(args: Arguments, client: &Client) -> Result<ReturnResult> {
+    if !(1..=2).contains(&args.version) {
+        return Err(Error::InvalidArguments(format!("Unknown `version`: {}", args.version)));
+    }
+
+    let (format_id, _) = parse_title_id(&args.id)?;
+    let key = args.filter.cache_key(&format_id);
+    let episode_list = client.cache().get_or_fetch(client.settings(), Endpoint::EpisodeList, &key, args.bypass_cache, || {
+        episode_list_slice(client, &args.id, &args.filter)
+    })?;
+
+    if args.version == 1 {
+        return Ok(ReturnResult::V1 {
+            status: true,
+            message: if episode_list.found() { String::from("") } else { String::from("Episode list not found.") },
+            data: episode_list.legacy_data(),
+        });
+    }
+
+    Ok(ReturnResult::V2 {
+        status: true,
+        message: String::from("Success"),
+        version: args.version,
+        data: episode_list,
+    })
+}
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EpisodeList {
+    /// `seasons` is empty for shows without an episodes tab.
+    Series { seasons: Vec<Season> },
+    Movie { episode: EpisodeData },
+}
 
-        // Check argument before processing
-        if arguments_ptr.is_null() {
-            panic!("Expected 1 argument.");
+impl EpisodeList {
+    /// Whether there are episodes to pick from, as opposed to a movie or an empty show.
+    pub fn found(&self) -> bool {
+        matches!(self, EpisodeList::Series { seasons } if !seasons.is_empty())
+    }
+
+    /// The original `data[season][0][episode]` shape.
+    pub fn legacy_data(&self) -> Vec<Vec<Vec<EpisodeData>>> {
+        match self {
+            EpisodeList::Series { seasons } => seasons.iter()
+                .map(|season| vec![season.episodes.clone()])
+                .collect(),
+            EpisodeList::Movie { episode } => vec![vec![vec![episode.clone()]]],
         }
+    }
+}
 
-        let args: Arguments = unsafe { 
-            from_str(&CStr::from_ptr(arguments_ptr as *mut c_char).to_string_lossy().into_owned()).unwrap()
-        };
-        
-        // ================================================
+/// Which part of a series to scrape. The default is everything.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EpisodeFilter {
+    /// Season number as listed by simkl, `0` for specials.
+    #[serde(default)]
+    pub season: Option<usize>,
+    #[serde(default)]
+    pub episode_range: Option<EpisodeRange>,
+}
 
+/// Inclusive bounds on the episode numbers shown by simkl.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EpisodeRange {
+    pub from: Option<usize>,
+    pub to: Option<usize>,
+}
 
-        let format_id = decode(&args.id).unwrap().to_string();
-        let id_type = format_id.split("/").nth(1).unwrap();
-        
+impl EpisodeFilter {
+    fn matches_season(&self, season: usize) -> bool {
+        self.season.is_none_or(|wanted| wanted == season)
+    }
+
+    fn matches_episode(&self, episode: usize) -> bool {
+        self.episode_range.is_none_or(|range| {
+            range.from.is_none_or(|from| episode >= from) && range.to.is_none_or(|to| episode <= to)
+        })
+    }
 
-        let client = reqwest::blocking::Client::new();
-        let mut headers = HeaderMap::new();
-        
-        headers.insert(USER_AGENT, HeaderValue::from_static(
-            "Chrome/123.0.0.0"
-        ));
+    /// Response cache key of this slice of the title `id`.
+    fn cache_key(&self, id: &str) -> String {
+        if *self == EpisodeFilter::default() {
+            return id.to_string();
+        }
 
+        let bound = |bound: Option<usize>| bound.map(|bound| bound.to_string()).unwrap_or_default();
+        let range = self.episode_range.unwrap_or_default();
 
-        /* Get related link ID */
+        format!("{}?season={}&episodes={}-{}", id, bound(self.season), bound(range.from), bound(range.to))
+    }
+}
 
-        let link_id = get_link_id(&format_id);
+/// A season heading without its episodes, as returned by `get_season_list`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeasonInfo {
+    pub number: Option<usize>,
+    pub title: String,
+    pub episode_count: usize,
+    pub poster: Option<String>,
+}
 
-        /* --- */
+/// Scrape the episodes of the simkl title `id`, as returned by `search`.
+pub fn episode_list(client: &Client, id: &str) -> Result<EpisodeList> {
+    episode_list_slice(client, id, &EpisodeFilter::default())
+}
 
+/// Scrape the episodes of the simkl title `id` that pass `filter`.
+///
+/// Seasons left without episodes are dropped. `episode_count` still counts the whole season.
+pub fn episode_list_slice(client: &Client, id: &str, filter: &EpisodeFilter) -> Result<EpisodeList> {
+    let (format_id, id_type) = parse_title_id(id)?;
 
-        
-        let url = format!("https://{}{}/episodes", 
-            SOURCE_HOST, format_id
-        );
 
-        
+    /* Get related link ID */
 
-        let res = client.get(&url).headers(headers).send().unwrap();
-        
-        if !res.status().is_success(){
-            panic!("Error: {}", res.status());
+    let link_id = get_link_id(client, &format_id)?;
+    require_playable(&link_id)?;
+
+    /* --- */
+
+
+    if let Some(html) = episodes_page(client, &format_id, &id_type)? {
+        let seasons = parse_seasons(&html, Some(&EpisodeContext {
+            title_id: &format_id,
+            id_type: &id_type,
+            link_id: &link_id,
+            filter,
+        }))?;
+
+        // Anime movies are listed under `anime` too, but without any episode.
+        if id_type != "anime" || !seasons.is_empty() || !parse_seasons(&html, None)?.is_empty() {
+            return Ok(EpisodeList::Series { seasons });
         }
+    }
 
-        let html = res.text().unwrap();
-
-        let vis = Vis::load(html).unwrap();
-
-        
-
-
-        if id_type == "tv" {
-            let ep_tab_ele = vis.find("#InfoTabsEpisodes");
-
-            if ep_tab_ele.length() == 0 {
-                return_result.data = Vec::new();
-                
-                return_result.message = String::from("Episode list not found.");
-            }else{
-                
-                let details_ele = vis.find(".SimklTVEpisodesBlock").find(".SimklTVAboutTabsDetails");
-                
-                if details_ele.length() > 0 {
-                    
-                    let tr_ele = vis.find("tr");
-                    
-                    let mut season_index = 0;
-                    for tr in tr_ele {
-                        let tr_ele = Vis::dom(&tr);
-
-                        let is_not_ep_ele = tr_ele.find(".SimklTVAboutTabsDetailsSeasonHead");
-                        if is_not_ep_ele.length() > 0 {
-                            continue;
-                        }
-
-                        let ep_ele = tr_ele.find("td").find("div.goEpisode");
-                        if ep_ele.length() > 0 {
-                            let mut new_ep_data: Vec<EpisodeData> = Vec::new();
-
-                            let mut episode_index = 0;
-                            for ep in ep_ele.into_iter() {
-                                let ep_ele = Vis::dom(&ep);
-                                
-                                let raw_title = ep_ele.find(".SimklTVEpisodesEpTitle").text();
-                                let title = decode_html_entities(&raw_title).to_string();
-                                let id = encode(&to_string(&json!({
-                                        "id_type": id_type,
-                                        "imdb_id": link_id.imdb_id,
-                                        "tmdb_id": link_id.tmdb_id,
-                                        "s": season_index,
-                                        "e": episode_index,
-                                    })).unwrap()).to_string();
-                                new_ep_data.push(EpisodeData {
-                                    index: episode_index,
-                                    id: id,
-                                    title: title,
-                                });
-
-                                episode_index += 1;
-                            }
-
-                            season_index += 1;
-                            return_result.data.push(vec![new_ep_data]);
-                        }
-                        
+    Ok(EpisodeList::Movie {
+        episode: EpisodeData {
+            index: 0,
+            id: encode_episode_id(Some(&format_id), &id_type, &link_id, None)?,
+            title: String::from("Full"),
+            season: None,
+            number: None,
+            air_date: None,
+            image: None,
+            overview: None,
+            aired: true
+        },
+    })
+}
+
+/// List the seasons of the simkl title `id` with their episode counts, without parsing the episodes.
+///
+/// Empty for movies.
+pub fn season_list(client: &Client, id: &str) -> Result<Vec<SeasonInfo>> {
+    let (format_id, id_type) = parse_title_id(id)?;
+
+    let Some(html) = episodes_page(client, &format_id, &id_type)? else {
+        return Ok(Vec::new());
+    };
+    let seasons = parse_seasons(&html, None)?;
+
+    Ok(seasons.into_iter()
+        .map(|season| SeasonInfo {
+            number: season.number,
+            title: season.title,
+            episode_count: season.episode_count,
+            poster: season.poster,
+        })
+        .collect())
+}
+
+/// Decode a search item id into its simkl path and type, e.g. `/tv/1074318/loki` and `tv`.
+///
+/// `tv` and `anime` titles are series, anything else a movie.
+fn parse_title_id(id: &str) -> Result<(String, String)> {
+    let format_id = decode(id)
+        .map_err(|e| Error::InvalidArguments(e.to_string()))?
+        .to_string();
+    let id_type = format_id.split("/").nth(1)
+        .filter(|id_type| !id_type.is_empty())
+        .ok_or_else(|| Error::InvalidArguments(format!("Malformed id: {}", format_id)))?
+        .to_string();
+
+    Ok((format_id, id_type))
+}
+
+/// The episodes page of a `tv` or `anime` title, `None` for a movie.
+///
+/// An anime movie may have no episodes page at all.
+fn episodes_page(client: &Client, format_id: &str, id_type: &str) -> Result<Option<String>> {
+    if !matches!(id_type, "tv" | "anime") {
+        return Ok(None);
+    }
+
+    let url = format!("{}{}/episodes",
+        client.settings().source_base_url, format_id
+    );
+
+    match client.get(&url, HeaderMap::new()) {
+        Ok(html) => Ok(Some(html)),
+        Err(Error::UpstreamHttp { status: 404, .. }) if id_type == "anime" => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// What it takes to build the episodes of a title.
+struct EpisodeContext<'a> {
+    title_id: &'a str,
+    id_type: &'a str,
+    link_id: &'a LinkID,
+    filter: &'a EpisodeFilter,
+}
+
+/// Read the seasons out of an episodes page.
+///
+/// With `episodes`, the episodes passing its filter are parsed too; without, only the headings and counts.
+fn parse_seasons(html: &str, episodes: Option<&EpisodeContext>) -> Result<Vec<Season>> {
+    let mut seasons: Vec<Season> = Vec::new();
+
+    let vis = Vis::load(html).map_err(|e| Error::ParseFailed(e.to_string()))?;
+
+    let ep_tab_ele = vis.find("#InfoTabsEpisodes");
+    let details_ele = vis.find(".SimklTVEpisodesBlock").find(".SimklTVAboutTabsDetails");
+
+    if ep_tab_ele.length() == 0 || details_ele.length() == 0 {
+        return Ok(seasons);
+    }
+
+    let tr_ele = vis.find("tr");
+
+    let today = today();
+    let mut season_index = 0;
+    let mut season = None;
+    let mut season_title = String::new();
+    let mut season_poster = None;
+    for tr in tr_ele {
+        let tr_ele = Vis::dom(&tr);
+
+        let is_not_ep_ele = tr_ele.find(".SimklTVAboutTabsDetailsSeasonHead");
+        if is_not_ep_ele.length() > 0 {
+            season_title = decode_html_entities(is_not_ep_ele.text().trim()).to_string();
+            season = parse_season(&season_title);
+            season_poster = is_not_ep_ele.find("img").attr("src")
+                .map(|src| src.to_string())
+                .filter(|src| !src.is_empty());
+            continue;
+        }
+
+        let ep_ele = tr_ele.find("td").find("div.goEpisode");
+        if ep_ele.length() == 0 {
+            continue;
+        }
+
+        let season_number = season.unwrap_or(season_index + 1);
+        season_index += 1;
+
+        let mut new_ep_data: Vec<EpisodeData> = Vec::new();
+        let episode_count = ep_ele.length();
+
+        match episodes {
+            Some(context) if !context.filter.matches_season(season_number) => continue,
+            Some(context) => {
+                for (episode_index, ep) in ep_ele.into_iter().enumerate() {
+                    let ep_ele = Vis::dom(&ep);
+
+                    // The numbers simkl shows, which skip and repeat where the show does; positions only order the list.
+                    let number = leading_number(&ep_ele.find(".SimklTVEpisodesEpNumber").text());
+                    let episode_number = number.unwrap_or(episode_index + 1);
+                    if !context.filter.matches_episode(episode_number) {
+                        continue;
                     }
+
+                    let raw_title = ep_ele.find(".SimklTVEpisodesEpTitle").text();
+                    let title = decode_html_entities(raw_title.trim()).to_string();
+                    let id = encode_episode_id(Some(context.title_id), context.id_type, context.link_id, Some((season_number, episode_number)))?;
+
+                    let date_ele = ep_ele.find(".SimklTVEpisodesEpDate");
+                    let air_date = date_ele.attr("content")
+                        .map(|date| date.to_string())
+                        .filter(|date| is_iso_date(date));
+
+                    let overview = decode_html_entities(ep_ele.find(".SimklTVEpisodesEpDescription").text().trim()).to_string();
+
+                    new_ep_data.push(EpisodeData {
+                        index: episode_index,
+                        id,
+                        title,
+                        season: Some(season_number),
+                        number,
+                        aired: air_date.as_ref().is_none_or(|date| *date <= today),
+                        air_date,
+                        image: ep_ele.find("img.SimklTVEpisodesEpImage").attr("src")
+                            .map(|src| src.to_string())
+                            .filter(|src| !src.is_empty()),
+                        overview: (!overview.is_empty()).then_some(overview),
+                    });
                 }
 
-            }
-        }else{
-            return_result.data = vec![vec![vec![EpisodeData { 
-                index: 0, 
-                id: encode(&to_string(&json!({
-                    "id_type": id_type,
-                    "imdb_id": link_id.imdb_id,
-                    "tmdb_id": link_id.tmdb_id
-                })).unwrap()).to_string(), 
-                title: String::from("Full") 
-            }]]];
-            return_result.message = String::from("Episode list not found.");
+                if new_ep_data.is_empty() {
+                    continue;
+                }
+            },
+            None => {},
         }
-        
-        
-        return_result.status = true;
-        return serde_json::to_string(&return_result).unwrap();
-    });
-    
-    match result {
-        Ok(data) => {
-            let result = CString::new(data).unwrap();
-            return result.into_raw();
-        },
-        _ => ptr::null(),
+
+        seasons.push(Season {
+            number: Some(season_number),
+            title: season_title.clone(),
+            episode_count,
+            poster: season_poster.clone(),
+            episodes: new_ep_data,
+        });
     }
+
+    Ok(seasons)
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct LinkID {
-    imdb_id: String,
-    tmdb_id: String,
+/// `Season 2` is season 2, `Specials` season 0.
+fn parse_season(head: &str) -> Option<usize> {
+    if head.trim().to_lowercase().starts_with("special") {
+        return Some(0);
+    }
+
+    head.split_whitespace().find_map(leading_number)
+}
+
+/// The number `Episode 12` or `12.` starts with, after any label.
+fn leading_number(text: &str) -> Option<usize> {
+    let digits = text.trim()
+        .trim_start_matches(|c: char| !c.is_ascii_digit())
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .collect::<String>();
+
+    digits.parse().ok()
+}
+
+fn is_iso_date(date: &str) -> bool {
+    date.len() == 10 && date.chars().enumerate().all(|(i, c)| match i {
+        4 | 7 => c == '-',
+        _ => c.is_ascii_digit(),
+    })
+}
+
+/// Today's UTC date as `YYYY-MM-DD`, comparable with the air dates as strings.
+fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() / 86_400)
+        .unwrap_or_default() as i64;
+
+    // Civil date from days since 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Url-encoded JSON id of one episode, or of the movie when `numbers` (season, episode) is `None`.
+///
+/// `title_id` is the simkl path of the title, when known.
+pub(crate) fn encode_episode_id(title_id: Option<&str>, id_type: &str, link_id: &LinkID, numbers: Option<(usize, usize)>) -> Result<String> {
+    let mut episode_id = json!({
+        "title_id": title_id,
+        "id_type": id_type,
+        "imdb_id": link_id.imdb_id.clone().unwrap_or_default(),
+        "tmdb_id": link_id.tmdb_id.clone().unwrap_or_default(),
+        "ids": link_id,
+    });
+
+    if let Some((season, episode)) = numbers {
+        episode_id["season"] = json!(season);
+        episode_id["episode"] = json!(episode);
+    }
 
+    Ok(encode(&to_string(&episode_id).map_err(|e| Error::Internal(e.to_string()))?).to_string())
 }
 
-fn get_link_id(id: &str) -> LinkID {
+/// External ids of a title, as linked from its simkl page. Any of them may be missing.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LinkID {
+    pub simkl_id: Option<String>,
+    pub imdb_id: Option<String>,
+    pub tmdb_id: Option<String>,
+    pub tvdb_id: Option<String>,
+    pub mal_id: Option<String>,
+    pub anilist_id: Option<String>,
+    pub trakt_id: Option<String>,
+    /// Every other linked site, keyed by its lowercased label.
+    pub others: BTreeMap<String, String>,
+}
 
-    let url = format!("https://{}{}", SOURCE_HOST, id);
+impl LinkID {
+    /// Look an id up by name: `simkl`, `imdb`, `tmdb`, `tvdb`, `mal`, `anilist`, `trakt` or an `others` key.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        match name {
+            "simkl" => self.simkl_id.as_deref(),
+            "imdb" => self.imdb_id.as_deref(),
+            "tmdb" => self.tmdb_id.as_deref(),
+            "tvdb" => self.tvdb_id.as_deref(),
+            "mal" => self.mal_id.as_deref(),
+            "anilist" => self.anilist_id.as_deref(),
+            "trakt" => self.trakt_id.as_deref(),
+            _ => self.others.get(name).map(String::as_str),
+        }
+    }
 
-    
-    let client = reqwest::blocking::Client::new();
-    let mut headers = HeaderMap::new();
-    
-    headers.insert(USER_AGENT, HeaderValue::from_static(
-        "Chrome/123.0.0.0"
-    ));
+    /// Fail with `LINK_ID_NOT_FOUND` unless every id in `names` is present.
+    pub fn require(&self, names: &[&str]) -> Result<()> {
+        let missing = names.iter()
+            .filter(|name| self.get(name).is_none_or(str::is_empty))
+            .map(|name| name.to_uppercase())
+            .collect::<Vec<_>>();
 
-    
-    let res = client.get(&url)
-        .headers(headers).send().unwrap();
-    
-    if !res.status().is_success(){
-        panic!("Error: {}", res.status());
+        if !missing.is_empty() {
+            return Err(Error::LinkIdNotFound(format!("{} ID not found.", missing.join(", "))));
+        }
+
+        Ok(())
     }
 
-    let html = res.text().unwrap();
-    let vis = Vis::load(&html).unwrap();
+    fn insert(&mut self, label: &str, id: String) {
+        let label = label.trim().to_lowercase();
+        let slot = match label.as_str() {
+            "imdb" => &mut self.imdb_id,
+            "tmdb" => &mut self.tmdb_id,
+            "tvdb" => &mut self.tvdb_id,
+            "mal" | "myanimelist" => &mut self.mal_id,
+            "anilist" => &mut self.anilist_id,
+            "trakt" => &mut self.trakt_id,
+            "" => return,
+            _ => {
+                self.others.insert(label, id);
+                return;
+            },
+        };
+
+        slot.get_or_insert(id);
+    }
+}
+
+/// Read every external id linked from the simkl title page `id`.
+pub fn get_link_id(client: &Client, id: &str) -> Result<LinkID> {
+    let html = title_page(client, id, false)?;
+
+    parse_link_id(id, &html)
+}
+
+/// Read the external ids out of the title page of `id`. Never fails for a missing id, see `LinkID::require`.
+pub fn parse_link_id(id: &str, html: &str) -> Result<LinkID> {
+    let vis = Vis::load(html).map_err(|e| Error::ParseFailed(e.to_string()))?;
 
     let link_detail_ele = vis.find(".SimklTVAboutTabsDetailsLinks").find("a");
 
-    let mut imdb_id: String = String::new();
-    let mut tmdb_id: String = String::new();
+    let mut link_id = LinkID {
+        // `/tv/1074318/loki`
+        simkl_id: id.split("/").nth(2)
+            .filter(|simkl_id| !simkl_id.is_empty())
+            .map(|simkl_id| simkl_id.to_string()),
+        ..LinkID::default()
+    };
 
     for link in link_detail_ele {
         let link_ele = Vis::dom(&link);
         let link_text = link_ele.text();
-        if link_text.trim() == "IMDB" {
-            let imdb_url = link_ele.attr("href").unwrap().to_string();
-            let split_imdb_url = imdb_url.split("/").collect::<Vec<_>>();
-            imdb_id = split_imdb_url[split_imdb_url.len() - 2].to_string();
-        }else if link_text.trim() == "TMDB" {
-            let tmdb_url = link_ele.attr("href").unwrap().to_string();
-            let split_tmdb_url = tmdb_url.split("/").collect::<Vec<_>>();
-            tmdb_id = split_tmdb_url[split_tmdb_url.len() - 1].to_string();
-            
+        let Some(href) = link_ele.attr("href").map(|v| decode_html_entities(&v.to_string()).to_string()) else {
+            continue;
+        };
+
+        if let Some(external_id) = id_from_href(&href) {
+            link_id.insert(&link_text, external_id);
         }
     }
 
-    if tmdb_id.is_empty() || imdb_id.is_empty() {
-        panic!("Error: TMDB or IMDB ID not found.");
+    Ok(link_id)
+}
+
+/// The id in a link to an external site: its `id` query parameter, else the first numeric or
+/// IMDB style (`tt…`) path segment, else the last path segment (e.g. a Trakt slug).
+fn id_from_href(href: &str) -> Option<String> {
+    let url = Url::parse(href).ok()?;
+
+    if let Some((_, id)) = url.query_pairs().find(|(key, _)| key == "id") {
+        return Some(id.into_owned());
     }
 
-    return LinkID {
-        imdb_id,
-        tmdb_id,
-    };
-}
\ No newline at end of file
+    let segments = url.path_segments()?.filter(|segment| !segment.is_empty()).collect::<Vec<_>>();
+
+    segments.iter()
+        .find_map(|segment| {
+            let digits = segment.chars().take_while(|c| c.is_ascii_digit()).collect::<String>();
+            (!digits.is_empty()).then_some(digits)
+        })
+        .or_else(|| segments.iter()
+            .find(|segment| segment.strip_prefix("tt").is_some_and(|rest| !rest.is_empty() && rest.chars().all(|c| c.is_ascii_digit())))
+            .map(|segment| segment.to_string()))
+        .or_else(|| segments.last().map(|segment| segment.to_string()))
+}
+
+/// HTML of the simkl title page `id` (decoded, e.g. `/tv/1074318/loki`), through the response cache.
+///
+/// `get_details` and `get_link_id` both read it, so whichever runs second does not fetch it again.
+pub fn title_page(client: &Client, id: &str, bypass_cache: bool) -> Result<String> {
+    client.cache().get_or_fetch(client.settings(), Endpoint::TitlePage, id, bypass_cache, || {
+        let url = format!("{}{}", client.settings().source_base_url, id);
+
+        client.get(&url, HeaderMap::new())
+    })
+}
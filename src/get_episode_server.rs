@@ -1,21 +1,49 @@
+use std::cmp::Reverse;
 use std::collections::HashMap;
-use std::ffi::{CString, CStr};
+use std::ffi::c_void;
 use std::os::raw::{c_char};
-use std::panic;
-use std::ptr;
+use std::thread;
+use std::time::{Duration, Instant};
 
+use reqwest::header::{HeaderMap, ORIGIN, REFERER};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use serde_json::{from_str, Value, to_string};
 use urlencoding::{decode, encode};
 
+use crate::client::Client;
+use crate::error::{Error, ErrorInfo, Result};
+use crate::get_server::{EpisodeInfo, GetServerResult};
+use crate::provider::{provider, MAX_MIRRORS, PROVIDERS};
+use crate::utils::ffi;
+use crate::utils::http::header_value;
 
 
-#[derive(Debug, Serialize, Deserialize)]
-struct EpisodeServerData{
-    index: usize,
-    id: String,
-    title: String,
-    verify_url: Option<String>,
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EpisodeServerData{
+    /// Mirror of the server the id points at, to pass back as the `index` of `get_server`.
+    pub index: usize,
+    pub id: String,
+    pub title: String,
+    /// How many mirrors the server has, each listed as its own entry. `None` when its provider cannot tell
+    /// without asking for each one, which `probe` does, so only its first mirror is listed.
+    pub mirrors: Option<usize>,
+    pub verify_url: Option<String>,
+    /// Only set when asked to `probe`.
+    pub probe: Option<Probe>,
+}
+
+/// How a server fared when resolved ahead of time.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Probe {
+    pub available: bool,
+    /// Time taken to resolve the stream and read its playlist, or to fail.
+    pub latency_ms: u64,
+    /// Height of the best rendition, e.g. `1080`, when it could be told.
+    pub max_quality: Option<u32>,
+    /// Why the server is not available.
+    pub error: Option<ErrorInfo>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -27,7 +55,10 @@ struct ReturnResult {
 
 #[derive(Serialize, Deserialize)]
 struct Arguments {
-    episode_id: String
+    episode_id: String,
+    /// Resolve every server before answering, and sort them best first.
+    #[serde(default)]
+    probe: bool,
 }
 
 
@@ -35,80 +66,232 @@ struct Arguments {
 pub extern "C" fn get_episode_server(
     arguments_ptr : *const c_char,
 ) -> *const c_char {
-    let result = panic::catch_unwind(|| {
-        let mut return_result = ReturnResult {
-            status: false,
-            message: String::from(""),
-            data: HashMap::new(),
-            
-        };
+    ffi::call("Get Episode Server", arguments_ptr, run)
+}
+
+/// Non-blocking `get_episode_server`: returns a request handle and hands the envelope to `callback` from a worker thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn get_episode_server_async(
+    arguments_ptr : *const c_char,
+    callback: Option<ffi::Callback>,
+    user_data: *mut c_void,
+) -> u64 {
+    ffi::call_async("Get Episode Server", arguments_ptr, callback, user_data, run)
+}
 
-        // Check argument before processing
-        if arguments_ptr.is_null() {
-            panic!("Expected 1 argument.");
+fn run(args: Arguments, client: &Client) -> Result<ReturnResult> {
+    let mut data = episode_servers(&args.episode_id)?;
+
+    if args.probe {
+        for servers in data.values_mut() {
+            probe_servers(client, servers);
         }
+    }
 
-        let args: Arguments = unsafe { 
-            from_str(&CStr::from_ptr(arguments_ptr as *mut c_char).to_string_lossy().into_owned()).unwrap()
-        };
-        
-        // ================================================
-
-        
-
-        let raw_episode_id = args.episode_id;
-        let mut episode_info: Value = from_str(&decode(&raw_episode_id).unwrap()).unwrap();
-
-
-
-        let mut server_data = vec![];
-
-        episode_info.as_object_mut().unwrap().insert(String::from("source_id"), Value::String(String::from("sflix2")));
-        server_data.push(EpisodeServerData{
-            id: encode(&to_string(&episode_info).unwrap()).to_string(),
-            index: 0,
-            title: "SFlix2".to_string(),
-            verify_url: None
-        });
-
-        // episode_info.as_object_mut().unwrap().insert(String::from("source_id"), Value::String(String::from("m4uhd")));
-        // server_data.push(EpisodeServerData{
-        //     id: encode(&to_string(&episode_info).unwrap()).to_string(),
-        //     index: 0,
-        //     title: "M4UHD".to_string(),
-        //     verify_url: None
-        // });
-
-        // episode_info.as_object_mut().unwrap().insert(String::from("source_id"), Value::String(String::from("bmovies")));
-        // server_data.push(EpisodeServerData{
-        //     id: encode(&to_string(&episode_info).unwrap()).to_string(),
-        //     index: 0,
-        //     title: "BMovies".to_string(),
-        //     verify_url: None
-        // });
-
-        // episode_info.as_object_mut().unwrap().insert(String::from("source_id"), Value::String(String::from("insertunit")));
-        // server_data.push(EpisodeServerData{
-        //     id: encode(&to_string(&episode_info).unwrap()).to_string(),
-        //     index: 0,
-        //     title: "Insertunit".to_string(),
-        //     verify_url: None
-        // });
-        
-        return_result.data.insert(String::from("SERVER"), server_data);
-
-        return_result.status = true;
-        return_result.message = String::from("Success");
-        
-        
-        return serde_json::to_string(&return_result).unwrap();
+    Ok(ReturnResult {
+        status: true,
+        message: String::from("Success"),
+        data,
+    })
+}
+
+
+/// List the servers every provider offers for `episode_id`, as returned by `get_episode_list`, one entry per mirror.
+pub fn episode_servers(episode_id: &str) -> Result<HashMap<String, Vec<EpisodeServerData>>> {
+    let parsed_episode_info = EpisodeInfo::decode(episode_id)?;
+
+    let decoded_episode_id = decode(episode_id)
+        .map_err(|e| Error::InvalidArguments(e.to_string()))?;
+    let mut episode_info: Value = from_str(&decoded_episode_id)
+        .map_err(|e| Error::InvalidArguments(e.to_string()))?;
+    let episode_info_map = episode_info.as_object_mut()
+        .ok_or_else(|| Error::InvalidArguments(String::from("`episode_id` is not an object.")))?;
+
+
+
+    let mut server_data = vec![];
+
+    for provider in PROVIDERS {
+        episode_info_map.insert(String::from("provider"), Value::String(provider.id().to_string()));
+
+        for server in provider.servers(&parsed_episode_info) {
+            episode_info_map.insert(String::from("source_id"), Value::String(server.source_id));
+
+            for srv in 0..server.mirrors.unwrap_or(1) {
+                episode_info_map.insert(String::from("srv"), Value::from(srv));
+
+                server_data.push(EpisodeServerData{
+                    id: encode(&to_string(&episode_info_map).map_err(|e| Error::Internal(e.to_string()))?).to_string(),
+                    index: srv,
+                    title: mirror_title(&server.title, srv),
+                    mirrors: server.mirrors,
+                    verify_url: None,
+                    probe: None,
+                });
+            }
+        }
+    }
+
+    let mut data = HashMap::new();
+    data.insert(String::from("SERVER"), server_data);
+
+    Ok(data)
+}
+
+/// `SFlix2` for the first mirror, `SFlix2 (Mirror 2)` for the next one.
+fn mirror_title(title: &str, srv: usize) -> String {
+    match srv {
+        0 => title.to_string(),
+        _ => format!("{} (Mirror {})", title, srv + 1),
+    }
+}
+
+/// What probing one mirror tells of the ones after it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mirror {
+    /// It exists, whether it plays or not.
+    Found,
+    /// It has no stream, nor will the ones after it.
+    Missing,
+    /// What every mirror shares failed, or the probes ran out of time, which tells nothing of it.
+    Unknown,
+}
+
+/// Resolve every server at once, within `probe_timeout_ms` or what is left of the call's deadline if that is sooner,
+/// and sort them best first:
+/// available ones before the rest, then by max quality and latency.
+///
+/// The mirrors of a server listed without its mirror count are found by probing them too, see `probe_mirrors`.
+pub fn probe_servers(client: &Client, servers: &mut Vec<EpisodeServerData>) {
+    let client = client.clone().with_deadline(Duration::from_millis(client.settings().probe_timeout_ms));
+
+    let mut probed = vec![Vec::new(); servers.len()];
+
+    thread::scope(|scope| {
+        for (server, probed) in servers.drain(..).zip(probed.iter_mut()) {
+            let client = &client;
+            scope.spawn(move || *probed = probe_mirrors(client, server));
+        }
     });
-    
-    match result {
-        Ok(data) => {
-            let result = CString::new(data).unwrap();
-            return result.into_raw();
+
+    *servers = probed.into_iter().flatten().collect();
+
+    servers.sort_by_key(|server| server.probe.as_ref()
+        .map(|probe| (!probe.available, Reverse(probe.max_quality), probe.latency_ms)));
+}
+
+/// Probe `server`, and when its mirror count is unknown, the mirrors after it one by one, up to `MAX_MIRRORS`,
+/// listing each one found and setting the count on all of them.
+///
+/// The first mirror without a stream ends them, and is only listed when it is the first, to show the server
+/// unavailable. A failure every mirror shares ends them too, leaving the count unknown.
+fn probe_mirrors(client: &Client, server: EpisodeServerData) -> Vec<EpisodeServerData> {
+    let episode_info = match EpisodeInfo::decode(&server.id) {
+        Ok(episode_info) => episode_info,
+        Err(e) => {
+            let probe = Probe { available: false, latency_ms: 0, max_quality: None, error: Some(e.info()) };
+            return vec![EpisodeServerData { probe: Some(probe), ..server }];
         },
-        _ => ptr::null(),
+    };
+
+    if server.mirrors.is_some() {
+        let (probe, _) = probe_server(client, &episode_info);
+        return vec![EpisodeServerData { probe: Some(probe), ..server }];
     }
+
+    let mut mirrors = Vec::new();
+    let mut count = None;
+
+    for srv in server.index..MAX_MIRRORS.max(server.index + 1) {
+        let mirror_info = EpisodeInfo { srv, ..episode_info.clone() };
+        let (probe, mirror) = probe_server(client, &mirror_info);
+
+        if mirror != Mirror::Missing || mirrors.is_empty() {
+            let Ok(id) = mirror_info.encode() else { break };
+
+            mirrors.push(EpisodeServerData {
+                index: srv,
+                id,
+                title: mirror_title(&server.title, srv),
+                probe: Some(probe),
+                ..server.clone()
+            });
+        }
+
+        count = match mirror {
+            Mirror::Found => Some(srv + 1),
+            Mirror::Missing => Some(srv),
+            Mirror::Unknown => None,
+        };
+        if mirror != Mirror::Found {
+            break;
+        }
+    }
+
+    for mirror in &mut mirrors {
+        mirror.mirrors = count;
+    }
+
+    mirrors
+}
+
+fn probe_server(client: &Client, episode_info: &EpisodeInfo) -> (Probe, Mirror) {
+    let started = Instant::now();
+
+    let resolved = provider(&episode_info.provider)
+        .and_then(|provider| provider.resolve(client, episode_info));
+
+    let mirror = match &resolved {
+        Err(_) if client.is_stopped() => Mirror::Unknown,
+        Err(Error::ServerFailed(e)) if matches!(**e, Error::ParseFailed(_)) => Mirror::Missing,
+        Ok(_) | Err(Error::ServerFailed(_)) => Mirror::Found,
+        Err(_) => Mirror::Unknown,
+    };
+
+    let max_quality = resolved
+        .map_err(Error::into_cause)
+        .and_then(|result| max_quality(client, &result));
+
+    let latency_ms = started.elapsed().as_millis() as u64;
+
+    let probe = match max_quality {
+        Ok(max_quality) => Probe { available: true, latency_ms, max_quality, error: None },
+        Err(e) => Probe { available: false, latency_ms, max_quality: None, error: Some(e.info()) },
+    };
+
+    (probe, mirror)
+}
+
+/// Height of the best rendition, from the master playlist of an HLS stream or else the file name.
+///
+/// Fetching the playlist also tells a stream that resolved but is already gone.
+fn max_quality(client: &Client, result: &GetServerResult) -> Result<Option<u32>> {
+    let source = result.data.sources.first()
+        .ok_or_else(|| Error::ParseFailed(String::from("No stream returned.")))?;
+
+    if source._type != "hls" {
+        return Ok(quality_in_name(&source.file));
+    }
+
+    let mut headers = HeaderMap::new();
+    headers.insert(REFERER, header_value(&result.config.referer)?);
+    headers.insert(ORIGIN, header_value(&result.config.origin)?);
+
+    let playlist = client.get(&source.file, headers)?;
+
+    let resolution_re = Regex::new(r"RESOLUTION=\d+x(\d+)").unwrap();
+
+    let max_quality = playlist.lines()
+        .filter(|line| line.starts_with("#EXT-X-STREAM-INF"))
+        .filter_map(|line| resolution_re.captures(line)?.get(1)?.as_str().parse::<u32>().ok())
+        .max();
+
+    Ok(max_quality.or_else(|| quality_in_name(&source.file)))
+}
+
+/// `1080` out of `.../1080p.mp4`.
+fn quality_in_name(url: &str) -> Option<u32> {
+    let quality_re = Regex::new(r"(\d{3,4})p\b").unwrap();
+
+    quality_re.captures(url)?.get(1)?.as_str().parse().ok()
 }
@@ -0,0 +1,59 @@
+use std::ffi::c_void;
+use std::os::raw::{c_char};
+
+use serde::{Deserialize, Serialize};
+use urlencoding::{decode};
+
+use crate::client::Client;
+use crate::error::{Error, Result};
+use crate::get_episode_list::{parse_link_id, title_page, LinkID};
+use crate::utils::ffi;
+
+
+
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ReturnResult {
+    status: bool,
+    message: String,
+    data: LinkID,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Arguments {
+    id: String,
+    /// Skip the response cache and fetch the title page again.
+    #[serde(default)]
+    bypass_cache: bool,
+}
+
+/// List the simkl, IMDB, TMDB, TVDB, MAL, AniList, Trakt and other ids of a search item.
+#[unsafe(no_mangle)]
+pub extern "C" fn get_external_ids(
+    arguments_ptr : *const c_char,
+) -> *const c_char {
+    ffi::call("Get External IDs", arguments_ptr, run)
+}
+
+/// Non-blocking `get_external_ids`: returns a request handle and hands the envelope to `callback` from a worker thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn get_external_ids_async(
+    arguments_ptr : *const c_char,
+    callback: Option<ffi::Callback>,
+    user_data: *mut c_void,
+) -> u64 {
+    ffi::call_async("Get External IDs", arguments_ptr, callback, user_data, run)
+}
+
+fn run(args: Arguments, client: &Client) -> Result<ReturnResult> {
+    let format_id = decode(&args.id)
+        .map_err(|e| Error::InvalidArguments(e.to_string()))?;
+
+    let html = title_page(client, &format_id, args.bypass_cache)?;
+
+    Ok(ReturnResult {
+        status: true,
+        message: String::from("Success"),
+        data: parse_link_id(&format_id, &html)?,
+    })
+}
@@ -0,0 +1,60 @@
+use std::ffi::c_void;
+use std::os::raw::{c_char};
+
+use serde::{Deserialize, Serialize};
+use urlencoding::{decode};
+
+use crate::cache::Endpoint;
+use crate::client::Client;
+use crate::error::{Error, Result};
+use crate::get_episode_list::{season_list, SeasonInfo};
+use crate::utils::ffi;
+
+
+
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ReturnResult {
+    status: bool,
+    message: String,
+    data: Vec<SeasonInfo>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Arguments {
+    id: String,
+    /// Skip the response cache and scrape the seasons again.
+    #[serde(default)]
+    bypass_cache: bool,
+}
+
+/// List the seasons of a search item with their episode counts, without its episodes.
+#[unsafe(no_mangle)]
+pub extern "C" fn get_season_list(
+    arguments_ptr : *const c_char,
+) -> *const c_char {
+    ffi::call("Get Season List", arguments_ptr, run)
+}
+
+/// Non-blocking `get_season_list`: returns a request handle and hands the envelope to `callback` from a worker thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn get_season_list_async(
+    arguments_ptr : *const c_char,
+    callback: Option<ffi::Callback>,
+    user_data: *mut c_void,
+) -> u64 {
+    ffi::call_async("Get Season List", arguments_ptr, callback, user_data, run)
+}
+
+fn run(args: Arguments, client: &Client) -> Result<ReturnResult> {
+    let key = format!("{}#seasons", decode(&args.id).map_err(|e| Error::InvalidArguments(e.to_string()))?);
+    let data = client.cache().get_or_fetch(client.settings(), Endpoint::EpisodeList, &key, args.bypass_cache, || {
+        season_list(client, &args.id)
+    })?;
+
+    Ok(ReturnResult {
+        status: true,
+        message: if data.is_empty() { String::from("Season list not found.") } else { String::from("Success") },
+        data,
+    })
+}
@@ -1,16 +1,16 @@
-use std::ffi::{CString, CStr};
+use std::ffi::c_void;
+use std::ops::Range;
 use std::os::raw::c_char;
-use std::panic;
-use std::ptr;
-use urlencoding::{decode};
-use reqwest::{header::{HeaderMap, HeaderValue, HOST, REFERER, ORIGIN, USER_AGENT, HeaderName, ACCEPT_ENCODING}};
-use url::Url;
-use visdom::Vis;
-use regex::Regex;
+use urlencoding::{decode, encode};
 use serde::{Deserialize, Serialize};
-use serde_json::{from_str, json, Value, from_value};
+use serde_json::{from_str, to_string};
 
-use crate::utils::aes;
+use crate::client::Client;
+use crate::error::{Error, FailedServer, Result};
+use crate::get_episode_list::LinkID;
+use crate::provider::moviesapi::MoviesApi;
+use crate::provider::{provider, Server, StreamProvider, MAX_MIRRORS, PROVIDERS};
+use crate::utils::ffi;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Timeline {
@@ -58,12 +58,33 @@ struct ServerInfo {
     message: String,
     data: Option<Data>,
     config: Option<Config>,
+    /// Set in `fallback` mode.
+    fallback: Option<Fallback>,
 }
 
 #[derive(Serialize, Deserialize)]
 struct Arguments {
     id: String,
-    index: usize
+    /// Mirror to resolve, as the `index` listed by `get_episode_server`. Defaults to the one in the id.
+    #[serde(default)]
+    index: Option<usize>,
+    /// Same as `index`, which it overrides.
+    #[serde(default)]
+    srv: Option<usize>,
+    /// Try the other servers when the chosen one fails.
+    #[serde(default)]
+    fallback: bool,
+}
+
+/// Which server `resolve_with_fallback` ended up playing, and why the ones before it did not.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fallback {
+    /// Episode id of the server that played, mirror included, to ask for it directly next time.
+    pub id: String,
+    pub provider: String,
+    pub source_id: String,
+    pub srv: usize,
+    pub errors: Vec<FailedServer>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -72,296 +93,207 @@ pub struct GetServerResult{
     pub config: Config
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct EpisodeInfo{
-    id_type: String,
-    imdb_id: String,
-    tmdb_id: String,
-    source_id: String,
-    s: Option<usize>,
-    e: Option<usize>
-}
-
-
-
-#[unsafe(no_mangle)]
-pub extern "C" fn get_server(
-    arguments_ptr: *const c_char,
-) -> *const c_char {
-    let result = panic::catch_unwind(|| {
-        let mut return_result = ServerInfo {
-            status: false,
-            message: String::from(""),
-            data: None,
-            config: None,
-        };
-
-        // Check argument before processing
-        if arguments_ptr.is_null() {
-            panic!("Expected 1 argument.");
-        }
-
-        let args: Arguments = unsafe { 
-            from_str(&CStr::from_ptr(arguments_ptr as *mut c_char).to_string_lossy().into_owned()).unwrap()
-        };
-        
-        // ================================================
-
-        let raw_episode_id = args.id;
-
-        let episode_info: EpisodeInfo = from_str(&decode(&raw_episode_id).unwrap()).unwrap();
-
-        let keys = get_keys(&episode_info);
-        
-
-        let hls_and_track = get_hls_and_track(&episode_info, &keys);
-
-    
-        
-        let parsed_hls_url = Url::parse(&hls_and_track.url).unwrap();
-        let config_host = parsed_hls_url.host().unwrap().to_string();
-
-        let data = Data {
-            intro: None,
-            outro: None,
-            sources: vec![SourceInfo {
-                file: hls_and_track.url,
-                _type: "hls".to_string()
-            }],
-            tracks: hls_and_track.tracks
-        };
-
-        let config = Config {
-            host: config_host.clone(),
-            referer: "https://ww2.moviesapi.to/".to_string(),
-            origin: "https://ww2.moviesapi.to".to_string(),
-            playlist_base_url: format!("https://{}", &config_host),
-            segment_base_url: format!("https://{}", &config_host)
-        };
-
-        return_result.status = true;
-        return_result.message = "Success".to_string();
-        return_result.data = Some(data);
-        return_result.config = Some(config);
-        
-        
-        
-        return serde_json::to_string(&return_result).unwrap();
-    });
-
-    match result {
-        Ok(data) => {
-            let result = CString::new(data).unwrap();
-            return result.into_raw();
-        },
-        _ => ptr::null(),
-    }
+/// Decoded form of the episode id handed out by `get_episode_server`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EpisodeInfo{
+    /// Simkl path of the title, e.g. `/tv/1074318/loki`. Missing from older ids.
+    #[serde(default)]
+    pub title_id: Option<String>,
+    pub id_type: String,
+    #[serde(default)]
+    pub imdb_id: String,
+    #[serde(default)]
+    pub tmdb_id: String,
+    /// Every external id of the title. Missing from ids handed out before they were collected.
+    #[serde(default)]
+    pub ids: LinkID,
+    /// Set by `get_episode_server`: the `StreamProvider` and which of its servers to resolve.
+    /// Ids handed out before there were several providers all came from moviesapi.
+    #[serde(default = "default_provider")]
+    pub provider: String,
+    /// Empty in the ids listed by `get_episode_list`.
+    #[serde(default)]
+    pub source_id: String,
+    /// Mirror of the server to resolve, scrapify's `srv` from `0`. Overridden by the `srv` or `index` passed to `get_server`.
+    #[serde(default)]
+    pub srv: usize,
+    /// Season and episode numbers as listed by simkl. `None` for movies.
+    #[serde(default)]
+    pub season: Option<usize>,
+    #[serde(default)]
+    pub episode: Option<usize>,
+    /// 0-based positions in the episode list, only found in ids handed out before the numbers were.
+    #[serde(default)]
+    pub s: Option<usize>,
+    #[serde(default)]
+    pub e: Option<usize>
 }
 
-
-#[derive(Debug, Serialize, Deserialize)]
-struct Keys{
-    scrapify_url: String,
-    encryption_key: String,
-    player_api_key: String
+fn default_provider() -> String {
+    String::from(MoviesApi::ID)
 }
 
-fn get_keys(episode_info: &EpisodeInfo) -> Keys {
-        let url:String;
-
-        if episode_info.e.is_some() && episode_info.s.is_some() {
-            url = format!("https://ww2.moviesapi.to/tv/{}/{}/{}",
-                episode_info.tmdb_id,
-                episode_info.s.unwrap()+1,
-                episode_info.e.unwrap()+1
-            );
-        }else{
-            url = format!("https://ww2.moviesapi.to/movie/{}",
-                episode_info.tmdb_id
-            );
-        }
-
-        let client = reqwest::blocking::Client::new();
-        let mut headers = HeaderMap::new();
-        
-        headers.insert(USER_AGENT, HeaderValue::from_static(
-            "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/142.0.0.0 Safari/537.36"
-        ));
-
-        headers.insert(HOST, HeaderValue::from_static("ww2.moviesapi.to"));
-        headers.insert(REFERER, HeaderValue::from_static("https://ww2.moviesapi.to/"));
-        headers.insert(ORIGIN, HeaderValue::from_static("https://ww2.moviesapi.to"));
+impl EpisodeInfo {
+    /// Parse the url-encoded JSON episode id.
+    pub fn decode(id: &str) -> Result<Self> {
+        let decoded_id = decode(id)
+            .map_err(|e| Error::InvalidArguments(e.to_string()))?;
 
-        /* Fetch Script URL */
-
-        let res = client.get(&url)
-            .headers(headers.clone()).send().unwrap();
-
-        if !res.status().is_success(){
-            panic!("Error: {}", res.status());
-        }
-
-        let html = res.text().unwrap();
-        let vis = Vis::load(&html).unwrap();
+        from_str(&decoded_id).map_err(|e| Error::InvalidArguments(e.to_string()))
+    }
 
-        let scripts = vis.find("script");
+    /// Url-encoded JSON, as handed out by `get_episode_server`.
+    pub fn encode(&self) -> Result<String> {
+        let json = to_string(self).map_err(|e| Error::Internal(e.to_string()))?;
 
-        let mut script_url = String::new();
+        Ok(encode(&json).to_string())
+    }
 
-        
-        for script in scripts {
-            let script_ele = Vis::dom(&script);
-            if let Some(src) = script_ele.attr("src") {
-                if let Some(script_type) = script_ele.attr("type") {
-                    if script_type.to_string() == "module" {
-                        script_url = format!("https://ww2.moviesapi.to{}", src);
-                    }
-                }
-            }
+    /// Pick the mirror `get_server` was asked for: its explicit `srv`, else its `index`, else the one in the id.
+    pub fn with_mirror(self, srv: Option<usize>, index: Option<usize>) -> Self {
+        EpisodeInfo {
+            srv: srv.or(index).unwrap_or(self.srv),
+            ..self
         }
+    }
 
-        if script_url.is_empty() {
-            panic!("Error: No `script_url` found");
+    /// The (season, episode) to ask moviesapi for, or `None` for a movie.
+    pub fn numbers(&self) -> Option<(usize, usize)> {
+        match (self.season, self.episode, self.s, self.e) {
+            (Some(season), Some(episode), _, _) => Some((season, episode)),
+            // Positions of the first season and episode listed, assumed to be 1 and 1.
+            (_, _, Some(s), Some(e)) => Some((s + 1, e + 1)),
+            _ => None,
         }
+    }
+}
 
-        /* --- */
-
-        /* Fetch Keys From Script URL */
 
-        let res = client.get(&script_url)
-            .headers(headers.clone()).send().unwrap();
 
-        if !res.status().is_success(){
-            panic!("Error: {}", res.status());
-        }
-        let script_text = res.text().unwrap();
-
-
-        
-        let scrapify_re = Regex::new(r#"SCRAPIFY_URL:\s*"([^"]+)""#).unwrap();
-        let encryption_re = Regex::new(r#"ENCRYPTION_KEY:\s*"([^"]+)""#).unwrap();
-        let player_re = Regex::new(r#"PLAYER_API_KEY:\s*"([^"]+)""#).unwrap();
-
-        
-        let scrapify_url = scrapify_re
-            .captures(&script_text)
-            .and_then(|cap| cap.get(1))
-            .map(|m| m.as_str().to_string())
-            .unwrap_or_default();
-
-        let encryption_key = encryption_re
-            .captures(&script_text)
-            .and_then(|cap| cap.get(1))
-            .map(|m| m.as_str().to_string())
-            .unwrap_or_default();
-
-        let player_api_key = player_re
-            .captures(&script_text)
-            .and_then(|cap| cap.get(1))
-            .map(|m| m.as_str().to_string())
-            .unwrap_or_default();
-
-        
-        let keys = Keys {
-            scrapify_url,
-            encryption_key,
-            player_api_key,
-        };
-        /* --- */
-        
-
-        return keys;
+#[unsafe(no_mangle)]
+pub extern "C" fn get_server(
+    arguments_ptr: *const c_char,
+) -> *const c_char {
+    ffi::call("Get Server", arguments_ptr, run)
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct RawEAS{
-    source: String,
-    id: String,
-    #[serde(rename = "type")]
-    _type: String,
-    season: Option<usize>,
-    episode: Option<usize>,
-    srv: String,
+/// Non-blocking `get_server`: returns a request handle and hands the envelope to `callback` from a worker thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn get_server_async(
+    arguments_ptr: *const c_char,
+    callback: Option<ffi::Callback>,
+    user_data: *mut c_void,
+) -> u64 {
+    ffi::call_async("Get Server", arguments_ptr, callback, user_data, run)
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct HlsAndTrack{
-    url: String,
-    tracks: Vec<TrackInfo>
+fn run(args: Arguments, client: &Client) -> Result<ServerInfo> {
+    let episode_info = EpisodeInfo::decode(&args.id)?.with_mirror(args.srv, args.index);
 
+    let (result, fallback) = match args.fallback {
+        true => {
+            let (result, fallback) = resolve_with_fallback(client, &episode_info)?;
+            (result, Some(fallback))
+        },
+        false => (resolve_server(client, &episode_info)?, None),
+    };
+
+    Ok(ServerInfo {
+        status: true,
+        message: "Success".to_string(),
+        data: Some(result.data),
+        config: Some(result.config),
+        fallback,
+    })
 }
 
 
-fn get_hls_and_track(episode_info: &EpisodeInfo, keys: &Keys) -> HlsAndTrack {
-    let raw_eas: RawEAS;
-    
-    if episode_info.s.is_some() && episode_info.e.is_some() {
-        raw_eas = RawEAS{
-            source: episode_info.source_id.clone(),
-            id: episode_info.tmdb_id.clone(),
-            _type: String::from("tv"),
-            season: Some(episode_info.s.unwrap()+1),
-            episode: Some(episode_info.e.unwrap()+1),
-            srv: String::from("0"),
-        };
-    }else{
-        raw_eas = RawEAS{
-            source: episode_info.source_id.clone(),
-            id: episode_info.tmdb_id.clone(),
-            _type: String::from("movie"),
-            season: episode_info.s,
-            episode: episode_info.e,
-            srv: String::from("0"),
-        };
-    }
-    
-    let encrypted_aes = aes::encrypt(&serde_json::to_string(&raw_eas).unwrap(), &keys.encryption_key);
-
-
+/// Resolve the playable stream and its playback config for `episode_info`, through the provider it names.
+pub fn resolve_server(client: &Client, episode_info: &EpisodeInfo) -> Result<GetServerResult> {
+    provider(&episode_info.provider)?.resolve(client, episode_info).map_err(Error::into_cause)
+}
 
-    let client = reqwest::blocking::Client::new();
-    let mut headers = HeaderMap::new();
-    
-    headers.insert(USER_AGENT, HeaderValue::from_static(
-        "Chrome/142.0.0.0 Safari/537.36"
-    ));
-    headers.insert(ACCEPT_ENCODING, HeaderValue::from_static("gzip, deflate, br"));
-    headers.insert(HOST, HeaderValue::from_static("ww2.moviesapi.to"));
-    headers.insert(REFERER, HeaderValue::from_static("https://ww2.moviesapi.to/"));
-    headers.insert(ORIGIN, HeaderValue::from_static("https://ww2.moviesapi.to"));
-    headers.insert(HeaderName::from_static("x-player-key"),
-        HeaderValue::from_str(&keys.player_api_key).unwrap()
-    );
-
-
-    let res = client.post("https://ww2.moviesapi.to/api/scrapify/v1/fetch")
-        .headers(headers)
-        .json(&json!({
-            "payload": encrypted_aes
-        }))
-        .send().unwrap();
-
-    if !res.status().is_success(){
-        panic!("Error: {}", res.status());
+/// Resolve `episode_info`, or else the first that plays of: the other mirrors of its server, the other servers
+/// of its provider and then those of the other providers, each from its first mirror.
+///
+/// Only `Error::ServerFailed` moves on to the next mirror, as long as the call is neither cancelled nor past its
+/// deadline; any other error is returned as is. A mirror without a stream ends the ones above it, see `Server::mirrors`.
+/// Fails with `Error::NoServerPlayed`, listing what each mirror failed with, when none plays.
+pub fn resolve_with_fallback(client: &Client, episode_info: &EpisodeInfo) -> Result<(GetServerResult, Fallback)> {
+    let mut errors: Vec<FailedServer> = Vec::new();
+
+    for (provider, run) in fallback_runs(episode_info)? {
+        for candidate in run {
+            let error = match provider.resolve(client, &candidate) {
+                Ok(result) => {
+                    let fallback = Fallback {
+                        id: candidate.encode()?,
+                        provider: candidate.provider,
+                        source_id: candidate.source_id,
+                        srv: candidate.srv,
+                        errors,
+                    };
+
+                    return Ok((result, fallback));
+                },
+                Err(Error::ServerFailed(e)) if !client.is_stopped() => e.into_cause(),
+                Err(e) => return Err(e.into_cause()),
+            };
+
+            let is_missing = matches!(error, Error::ParseFailed(_));
+            errors.push(FailedServer {
+                provider: candidate.provider,
+                source_id: candidate.source_id,
+                srv: candidate.srv,
+                error: error.info(),
+            });
+
+            if is_missing {
+                break;
+            }
+        }
     }
 
-    let mut raw_data:Value = res.json().unwrap();
+    Err(Error::NoServerPlayed(errors))
+}
 
-    for item in raw_data.get_mut("tracks").unwrap().as_array_mut().unwrap() {
-        item.as_object_mut().unwrap().insert("kind".to_string(), Value::String("subtitles".to_string()));
+/// Mirrors to try in order, in runs that stop at the first mirror without a stream: `episode_info` and the
+/// mirrors above it, then those below it, then every mirror of each other server, chosen provider first.
+fn fallback_runs(episode_info: &EpisodeInfo) -> Result<Vec<(&'static dyn StreamProvider, Vec<EpisodeInfo>)>> {
+    let chosen = provider(&episode_info.provider)?;
+
+    let is_chosen = |provider: &str, server: &Server| provider == episode_info.provider && server.source_id == episode_info.source_id;
+    let mirrors = |provider: &dyn StreamProvider, source_id: &str, srvs: Range<usize>| srvs
+        .map(|srv| EpisodeInfo {
+            provider: provider.id().to_string(),
+            source_id: source_id.to_string(),
+            srv,
+            ..episode_info.clone()
+        })
+        .collect::<Vec<_>>();
+
+    let chosen_mirrors = chosen.servers(episode_info).into_iter()
+        .find(|server| is_chosen(chosen.id(), server))
+        .and_then(|server| server.mirrors)
+        .unwrap_or(MAX_MIRRORS)
+        .max(episode_info.srv + 1);
+
+    let mut runs = vec![
+        (chosen, mirrors(chosen, &episode_info.source_id, episode_info.srv..chosen_mirrors)),
+        (chosen, mirrors(chosen, &episode_info.source_id, 0..episode_info.srv)),
+    ];
+
+    let mut providers = PROVIDERS.to_vec();
+    providers.sort_by_key(|provider| provider.id() != episode_info.provider);
+
+    for provider in providers {
+        for server in provider.servers(episode_info) {
+            if !is_chosen(provider.id(), &server) {
+                let run = mirrors(provider, &server.source_id, 0..server.mirrors.unwrap_or(MAX_MIRRORS));
+                runs.push((provider, run));
+            }
+        }
     }
 
-    let mut parse_data: HlsAndTrack = from_value(raw_data).unwrap();
-
-    let parsed = Url::parse(&parse_data.url).unwrap();
-
-    // Extract host + path
-    let host = parsed.host_str().unwrap_or("");
-    let path = parsed.path();
-
-    // Build the new URL
-    parse_data.url = format!("https://ax.1hd.su/{host}{path}");
-
-    return parse_data;
-
-}
\ No newline at end of file
+    Ok(runs)
+}
@@ -4,16 +4,36 @@ pub const SOURCE_HOST: &str = "simkl.com";
 pub const SOURCE_REFERER: &str = "https://simkl.com/";
 pub const SOURCE_ORIGIN: &str = "https://simkl.com";
 
-pub const SERVER_ORIGIN: &str = "https://simkl.com";
-pub const SERVER_REFERER: &str = "https://simkl.com/";
-pub const SERVER_HOST: &str = "simkl.com";
+pub const SERVER_ORIGIN: &str = "https://ww2.moviesapi.to";
+pub const SERVER_REFERER: &str = "https://ww2.moviesapi.to/";
+pub const SERVER_HOST: &str = "ww2.moviesapi.to";
 
+pub const SCRAPIFY_URL: &str = "https://ww2.moviesapi.to/api/scrapify/v1/fetch";
+pub const PROXY_HOST: &str = "ax.1hd.su";
+
+pub const DEFAULT_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/142.0.0.0 Safari/537.36";
+
+pub mod cache;
+pub mod client;
+pub mod config;
+pub mod error;
+pub mod runtime;
 pub mod search;
+pub mod get_adjacent_episodes;
+pub mod get_details;
 pub mod get_episode_list;
+pub mod get_external_ids;
 pub mod get_episode_server;
+pub mod get_season_list;
 pub mod get_server;
+pub mod resolve_id;
+pub mod set_config;
+pub mod cancel_request;
+pub mod clear_cache;
+pub mod get_cache_info;
+pub mod provider;
 pub mod free_ptr;
 pub mod utils;
 
 #[cfg(test)]
-mod test;
\ No newline at end of file
+mod test;
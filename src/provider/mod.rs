@@ -0,0 +1,72 @@
+pub mod moviesapi;
+
+use crate::client::Client;
+use crate::error::{Error, Result};
+use crate::get_episode_list::LinkID;
+use crate::get_server::{EpisodeInfo, GetServerResult};
+
+
+
+/// Most mirrors tried on a server whose provider does not know how many it has.
+pub const MAX_MIRRORS: usize = 4;
+
+/// One server a provider offers for an episode.
+#[derive(Debug, Clone)]
+pub struct Server {
+    /// Stored in the episode id as `source_id`.
+    pub source_id: String,
+    pub title: String,
+    /// How many mirrors it has, stored in the episode id as `srv` from `0`. `None` when only asking for each
+    /// mirror tells, in which case the ones it has come first and the others fail with `Error::ParseFailed`.
+    pub mirrors: Option<usize>,
+}
+
+/// A site playable streams are resolved from.
+///
+/// `get_episode_server` lists the servers of every provider in `PROVIDERS` and `get_server` resolves through
+/// the one named in the episode id, so adding a provider needs no new export.
+pub trait StreamProvider: Send + Sync {
+    /// Stored in the episode id as `provider`.
+    fn id(&self) -> &'static str;
+
+    /// External ids, as named by `LinkID::get`, a title must be linked to for this provider to find it.
+    fn required_ids(&self) -> &'static [&'static str];
+
+    /// Servers offered for `episode_info`, best first.
+    fn servers(&self, episode_info: &EpisodeInfo) -> Vec<Server>;
+
+    /// Stream and playback config of the server picked by `episode_info.source_id`.
+    ///
+    /// Fails with `Error::ServerFailed` when the request for that server failed without saying anything of the
+    /// others, e.g. it has no stream or timed out, but not when what every server shares failed.
+    fn resolve(&self, client: &Client, episode_info: &EpisodeInfo) -> Result<GetServerResult>;
+}
+
+/// Every provider, in the order `get_episode_server` lists them.
+pub static PROVIDERS: &[&dyn StreamProvider] = &[&moviesapi::MoviesApi];
+
+pub fn provider(id: &str) -> Result<&'static dyn StreamProvider> {
+    PROVIDERS.iter()
+        .copied()
+        .find(|provider| provider.id() == id)
+        .ok_or_else(|| Error::InvalidArguments(format!("Unknown `provider`: {}", id)))
+}
+
+/// Fail with what the first provider misses, unless some provider can look up a title linked to `link_id`.
+pub fn require_playable(link_id: &LinkID) -> Result<()> {
+    let mut first_error = None;
+
+    for provider in PROVIDERS {
+        match link_id.require(provider.required_ids()) {
+            Ok(()) => return Ok(()),
+            Err(e) => {
+                first_error.get_or_insert(e);
+            },
+        }
+    }
+
+    match first_error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
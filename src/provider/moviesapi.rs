@@ -0,0 +1,417 @@
+use reqwest::{header::{HeaderMap, HeaderValue, HOST, REFERER, ORIGIN, HeaderName}};
+use url::Url;
+use visdom::Vis;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{from_str, json, Value};
+
+use crate::client::Client;
+use crate::error::{Error, Result};
+use crate::get_server::{Config, Data, EpisodeInfo, GetServerResult, SourceInfo, TrackInfo};
+use crate::provider::{Server, StreamProvider};
+use crate::utils::aes;
+use crate::utils::http::header_value;
+
+
+
+
+/// Sites scrapify fetches streams from, as named by its `source` field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    Sflix2,
+    M4uhd,
+    Bmovies,
+    Insertunit,
+}
+
+impl Source {
+    /// In the order `get_episode_server` lists them.
+    pub const ALL: [Source; 4] = [Source::Sflix2, Source::M4uhd, Source::Bmovies, Source::Insertunit];
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            Source::Sflix2 => "sflix2",
+            Source::M4uhd => "m4uhd",
+            Source::Bmovies => "bmovies",
+            Source::Insertunit => "insertunit",
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Source::Sflix2 => "SFlix2",
+            Source::M4uhd => "M4UHD",
+            Source::Bmovies => "BMovies",
+            Source::Insertunit => "Insertunit",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|source| source.id() == id)
+    }
+}
+
+/// The source picked with `get_episode_server`.
+pub fn source(episode_info: &EpisodeInfo) -> Result<Source> {
+    match episode_info.source_id.as_str() {
+        "" => Err(Error::InvalidArguments(String::from("No `source_id`: pick a server with `get_episode_server` first."))),
+        id => Source::from_id(id).ok_or_else(|| Error::InvalidArguments(format!("Unknown `source_id`: {}", id))),
+    }
+}
+
+
+/// moviesapi's player: the keys from its module script encrypt a request to scrapify, which fetches from one of the `Source`s.
+#[derive(Debug, Clone, Copy)]
+pub struct MoviesApi;
+
+impl MoviesApi {
+    pub const ID: &'static str = "moviesapi";
+}
+
+impl StreamProvider for MoviesApi {
+    fn id(&self) -> &'static str {
+        Self::ID
+    }
+
+    /// moviesapi looks titles up by TMDB id.
+    fn required_ids(&self) -> &'static [&'static str] {
+        &["tmdb"]
+    }
+
+    fn servers(&self, _episode_info: &EpisodeInfo) -> Vec<Server> {
+        Source::ALL.into_iter()
+            .map(|source| Server {
+                source_id: source.id().to_string(),
+                title: source.title().to_string(),
+                // Scrapify only tells by answering for each `srv`.
+                mirrors: None,
+            })
+            .collect()
+    }
+
+    fn resolve(&self, client: &Client, episode_info: &EpisodeInfo) -> Result<GetServerResult> {
+        source(episode_info)?;
+        if episode_info.tmdb_id.is_empty() {
+            return Err(Error::LinkIdNotFound(String::from("TMDB ID not found.")));
+        }
+
+        let settings = client.settings();
+        let (keys, is_cached) = client.cache().keys_or_fetch(settings, Self::ID, &settings.server_base_url, || {
+            get_keys(client, episode_info)
+        })?;
+
+
+        let hls_and_track = match get_hls_and_track(client, episode_info, &keys) {
+            // Cached keys went stale: fetch them again, once.
+            Err(e) if is_cached && is_rejected(&e) => {
+                client.cache().invalidate_keys(settings, Some(Self::ID));
+
+                let (keys, _) = client.cache().keys_or_fetch(settings, Self::ID, &settings.server_base_url, || {
+                    get_keys(client, episode_info)
+                })?;
+
+                get_hls_and_track(client, episode_info, &keys)?
+            },
+            result => result?,
+        };
+
+
+
+        let parsed_hls_url = Url::parse(&hls_and_track.url)
+            .map_err(|e| server_failed(Error::ParseFailed(e.to_string())))?;
+        let config_host = parsed_hls_url.host_str()
+            .ok_or_else(|| server_failed(Error::ParseFailed(format!("No host in stream url: {}", hls_and_track.url))))?
+            .to_string();
+
+        let data = Data {
+            intro: None,
+            outro: None,
+            sources: vec![SourceInfo {
+                file: hls_and_track.url,
+                _type: hls_and_track._type
+            }],
+            tracks: hls_and_track.tracks
+        };
+
+        let config = Config {
+            host: config_host.clone(),
+            referer: client.settings().server_referer.clone(),
+            origin: client.settings().server_origin.clone(),
+            playlist_base_url: format!("https://{}", &config_host),
+            segment_base_url: format!("https://{}", &config_host)
+        };
+
+        Ok(GetServerResult { data, config })
+    }
+}
+
+
+/// Whether scrapify refused the payload, as it does once the encryption or player key rotates.
+fn is_rejected(error: &Error) -> bool {
+    matches!(error, Error::UpstreamHttp { status: 400 | 401 | 403 | 422, .. })
+}
+
+/// Mark what went wrong fetching one source from scrapify as that server's failure, unless it would fail
+/// every source alike: a cancelled call, or keys scrapify rejects.
+///
+/// The player page and the keys are shared by every source, so their failures are never marked.
+fn server_failed(error: Error) -> Error {
+    match error {
+        Error::Cancelled | Error::ServerFailed(_) => error,
+        _ if is_rejected(&error) => error,
+        _ => Error::ServerFailed(Box::new(error)),
+    }
+}
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Keys{
+    pub(crate) scrapify_url: String,
+    pub(crate) encryption_key: String,
+    pub(crate) player_api_key: String
+}
+
+pub(crate) fn get_keys(client: &Client, episode_info: &EpisodeInfo) -> Result<Keys> {
+        let settings = client.settings();
+        let url = match episode_info.numbers() {
+            Some((season, episode)) => format!("{}/tv/{}/{}/{}",
+                settings.server_base_url,
+                episode_info.tmdb_id,
+                season,
+                episode
+            ),
+            _ => format!("{}/movie/{}",
+                settings.server_base_url,
+                episode_info.tmdb_id
+            ),
+        };
+
+        let mut headers = HeaderMap::new();
+
+        headers.insert(HOST, header_value(&settings.server_host())?);
+        headers.insert(REFERER, header_value(&settings.server_referer)?);
+        headers.insert(ORIGIN, header_value(&settings.server_origin)?);
+
+        /* Fetch Script URL */
+
+        let html = client.get(&url, headers.clone())?;
+        let vis = Vis::load(&html).map_err(|e| Error::ParseFailed(e.to_string()))?;
+
+        let scripts = vis.find("script");
+
+        let mut script_url = String::new();
+
+
+        for script in scripts {
+            let script_ele = Vis::dom(&script);
+            if let (Some(src), Some(script_type)) = (script_ele.attr("src"), script_ele.attr("type"))
+                && script_type.to_string() == "module" {
+                script_url = format!("{}{}", settings.server_base_url, src);
+            }
+        }
+
+        if script_url.is_empty() {
+            return Err(Error::KeysNotFound(String::from("No `script_url` found")));
+        }
+
+        /* --- */
+
+        /* Fetch Keys From Script URL */
+
+        let script_text = client.get(&script_url, headers)?;
+
+
+
+        let scrapify_re = Regex::new(r#"SCRAPIFY_URL:\s*"([^"]+)""#).unwrap();
+        let encryption_re = Regex::new(r#"ENCRYPTION_KEY:\s*"([^"]+)""#).unwrap();
+        let player_re = Regex::new(r#"PLAYER_API_KEY:\s*"([^"]+)""#).unwrap();
+
+
+        let scrapify_url = scrapify_re
+            .captures(&script_text)
+            .and_then(|cap| cap.get(1))
+            .map(|m| m.as_str().to_string())
+            .unwrap_or_default();
+
+        let encryption_key = encryption_re
+            .captures(&script_text)
+            .and_then(|cap| cap.get(1))
+            .map(|m| m.as_str().to_string())
+            .ok_or_else(|| Error::KeysNotFound(String::from("No `ENCRYPTION_KEY` found")))?;
+
+        let player_api_key = player_re
+            .captures(&script_text)
+            .and_then(|cap| cap.get(1))
+            .map(|m| m.as_str().to_string())
+            .ok_or_else(|| Error::KeysNotFound(String::from("No `PLAYER_API_KEY` found")))?;
+
+
+        let keys = Keys {
+            scrapify_url,
+            encryption_key,
+            player_api_key,
+        };
+        /* --- */
+
+
+        Ok(keys)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RawEAS{
+    source: String,
+    id: String,
+    #[serde(rename = "type")]
+    _type: String,
+    season: Option<usize>,
+    episode: Option<usize>,
+    srv: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct HlsAndTrack{
+    pub(crate) url: String,
+    /// `hls` or `mp4`.
+    pub(crate) _type: String,
+    pub(crate) tracks: Vec<TrackInfo>
+
+}
+
+/// Fields of a scrapify response, each under the names sources answer with, preferred first.
+///
+/// Every source answers in the same loose format, differing only in which of these names it uses: the stream
+/// as a bare `url` or `file`, or as a list of `sources`, and the subtitles as `tracks` or `subtitles`,
+/// labelled by `label` or `lang`. A response may carry several of them at once; the first one set wins.
+const STREAM_URL: &[&str] = &["url", "file"];
+const STREAM_SOURCES: &[&str] = &["sources"];
+const STREAM_TRACKS: &[&str] = &["tracks", "subtitles"];
+const STREAM_ERROR: &[&str] = &["error", "message"];
+const SOURCE_URL: &[&str] = &["file", "url"];
+const TRACK_LABEL: &[&str] = &["label", "lang"];
+
+/// The first of `names` set in `object` to something other than `null`, an empty string or an empty list.
+fn field<'a>(object: &'a Value, names: &[&str]) -> Option<&'a Value> {
+    names.iter()
+        .filter_map(|name| object.get(name))
+        .find(|value| match value {
+            Value::Null => false,
+            Value::String(value) => !value.is_empty(),
+            Value::Array(values) => !values.is_empty(),
+            _ => true,
+        })
+}
+
+fn string_field(object: &Value, names: &[&str]) -> Option<String> {
+    field(object, names).and_then(Value::as_str).map(str::to_string)
+}
+
+
+pub(crate) fn get_hls_and_track(client: &Client, episode_info: &EpisodeInfo, keys: &Keys) -> Result<HlsAndTrack> {
+    let source = source(episode_info)?;
+    let raw_eas = match episode_info.numbers() {
+        Some((season, episode)) => RawEAS{
+            source: source.id().to_string(),
+            id: episode_info.tmdb_id.clone(),
+            _type: String::from("tv"),
+            season: Some(season),
+            episode: Some(episode),
+            srv: episode_info.srv.to_string(),
+        },
+        None => RawEAS{
+            source: source.id().to_string(),
+            id: episode_info.tmdb_id.clone(),
+            _type: String::from("movie"),
+            season: None,
+            episode: None,
+            srv: episode_info.srv.to_string(),
+        },
+    };
+
+    let payload = serde_json::to_string(&raw_eas).map_err(|e| Error::Internal(e.to_string()))?;
+    let encrypted_aes = aes::encrypt(&payload, &keys.encryption_key)?;
+
+
+
+    let settings = client.settings();
+    let mut headers = HeaderMap::new();
+
+    headers.insert(HOST, header_value(&settings.server_host())?);
+    headers.insert(REFERER, header_value(&settings.server_referer)?);
+    headers.insert(ORIGIN, header_value(&settings.server_origin)?);
+    headers.insert(HeaderName::from_static("x-player-key"),
+        HeaderValue::from_str(&keys.player_api_key)
+            .map_err(|e| Error::KeysNotFound(format!("Invalid `PLAYER_API_KEY`: {}", e)))?
+    );
+
+
+    let body = client.post(&settings.scrapify_url, headers, Some(&json!({
+            "payload": encrypted_aes
+        }))).map_err(server_failed)?;
+
+    let mut parse_data = parse_stream(source, &body).map_err(server_failed)?;
+
+    let parsed = Url::parse(&parse_data.url)
+        .map_err(|e| server_failed(Error::ParseFailed(e.to_string())))?;
+
+    // Extract host + path
+    let host = parsed.host_str().unwrap_or("");
+    let path = parsed.path();
+
+    // Build the new URL
+    if !settings.proxy_host.is_empty() {
+        parse_data.url = format!("https://{}/{host}{path}", settings.proxy_host);
+    }
+
+    Ok(parse_data)
+
+}
+
+/// Read the stream and subtitles out of what scrapify answered for `source`, which only names it in errors.
+pub(crate) fn parse_stream(source: Source, body: &str) -> Result<HlsAndTrack> {
+    let raw_stream: Value = from_str(body).map_err(|e| Error::ParseFailed(e.to_string()))?;
+    if !raw_stream.is_object() {
+        return Err(Error::ParseFailed(format!("{} returned no object: {}", source.title(), body)));
+    }
+
+    let first_source = field(&raw_stream, STREAM_SOURCES)
+        .and_then(Value::as_array)
+        .and_then(|sources| sources.iter().find(|raw_source| string_field(raw_source, SOURCE_URL).is_some()));
+
+    let (url, _type) = match (string_field(&raw_stream, STREAM_URL), first_source) {
+        (Some(url), _) => (url, None),
+        (None, Some(raw_source)) => (string_field(raw_source, SOURCE_URL).unwrap_or_default(), string_field(raw_source, &["type"])),
+        (None, None) => return Err(Error::ParseFailed(format!("{} returned no stream: {}",
+            source.title(),
+            string_field(&raw_stream, STREAM_ERROR).as_deref().unwrap_or("empty response")
+        ))),
+    };
+
+    let _type = _type
+        .map(|_type| _type.to_lowercase())
+        .unwrap_or_else(|| stream_type(&url).to_string());
+
+    let tracks = field(&raw_stream, STREAM_TRACKS)
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|track| Some(TrackInfo {
+            file: string_field(track, SOURCE_URL)?,
+            label: string_field(track, TRACK_LABEL),
+            kind: String::from("subtitles"),
+            default: track.get("default").and_then(Value::as_bool),
+        }))
+        .collect();
+
+    Ok(HlsAndTrack { url, _type, tracks })
+}
+
+/// `mp4` for a direct file, `hls` for anything else.
+fn stream_type(url: &str) -> &'static str {
+    let path = Url::parse(url).map(|url| url.path().to_lowercase()).unwrap_or_default();
+
+    match path.ends_with(".mp4") {
+        true => "mp4",
+        false => "hls",
+    }
+}
@@ -0,0 +1,290 @@
+use std::ffi::c_void;
+use std::os::raw::{c_char};
+
+use serde::{Deserialize, Serialize};
+use reqwest::header::{HeaderMap};
+use url::Url;
+use visdom::Vis;
+use urlencoding::{encode};
+
+use crate::cache::Endpoint;
+use crate::client::Client;
+use crate::error::{Error, Result};
+use crate::get_episode_list::{encode_episode_id, LinkID};
+use crate::utils::ffi;
+
+
+
+
+/// What an external id refers to, as TMDB and simkl number movies and shows separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IdType {
+    Movie,
+    Tv,
+}
+
+impl IdType {
+    /// First segment of the simkl path, as used in episode ids.
+    fn simkl_type(&self) -> &'static str {
+        match self {
+            IdType::Movie => "movies",
+            IdType::Tv => "tv",
+        }
+    }
+
+    fn tmdb_type(&self) -> &'static str {
+        match self {
+            IdType::Movie => "movie",
+            IdType::Tv => "tv",
+        }
+    }
+}
+
+/// A title as the host knows it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExternalId {
+    Imdb(String),
+    Tmdb { id: String, id_type: IdType },
+    Simkl(String),
+    /// Already a simkl path, e.g. `/tv/1074318/loki`.
+    SimklPath(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IdSource {
+    Imdb,
+    Tmdb,
+    Simkl,
+}
+
+impl ExternalId {
+    /// Recognise a bare id or a link to IMDB, TMDB or simkl.
+    ///
+    /// Without `source`, `tt…` is an IMDB id, and a bare number is a TMDB id when `id_type` is given and a simkl id otherwise.
+    pub fn parse(input: &str, source: Option<IdSource>, id_type: Option<IdType>) -> Result<Self> {
+        let input = input.trim();
+
+        if let Ok(url) = Url::parse(input) {
+            return Self::from_url(&url);
+        }
+
+        let missing_type = || Error::InvalidArguments(String::from("A TMDB id needs a `type`."));
+
+        match source {
+            Some(IdSource::Imdb) => Ok(ExternalId::Imdb(input.to_string())),
+            Some(IdSource::Tmdb) => Ok(ExternalId::Tmdb { id: input.to_string(), id_type: id_type.ok_or_else(missing_type)? }),
+            Some(IdSource::Simkl) => Ok(ExternalId::Simkl(input.to_string())),
+            None if is_imdb_id(input) => Ok(ExternalId::Imdb(input.to_string())),
+            None if is_number(input) => Ok(match id_type {
+                Some(id_type) => ExternalId::Tmdb { id: input.to_string(), id_type },
+                None => ExternalId::Simkl(input.to_string()),
+            }),
+            None => Err(Error::InvalidArguments(format!("Unrecognised id: {}", input))),
+        }
+    }
+
+    fn from_url(url: &Url) -> Result<Self> {
+        let host = url.host_str().unwrap_or_default().trim_start_matches("www.");
+        let segments = url.path_segments()
+            .map(|segments| segments.filter(|segment| !segment.is_empty()).collect::<Vec<_>>())
+            .unwrap_or_default();
+        let unsupported = || Error::InvalidArguments(format!("Unsupported link: {}", url));
+
+        match host {
+            // `https://www.imdb.com/title/tt9140554/`
+            "imdb.com" | "m.imdb.com" => segments.iter()
+                .find(|segment| is_imdb_id(segment))
+                .map(|segment| ExternalId::Imdb(segment.to_string()))
+                .ok_or_else(unsupported),
+            // `https://www.themoviedb.org/tv/84958-loki`
+            "themoviedb.org" => {
+                let id_type = match segments.first() {
+                    Some(&"movie") => IdType::Movie,
+                    Some(&"tv") => IdType::Tv,
+                    _ => return Err(unsupported()),
+                };
+                let id = segments.get(1)
+                    .map(|segment| segment.chars().take_while(|c| c.is_ascii_digit()).collect::<String>())
+                    .filter(|id| !id.is_empty())
+                    .ok_or_else(unsupported)?;
+
+                Ok(ExternalId::Tmdb { id, id_type })
+            },
+            // `https://simkl.com/tv/1074318/loki`, possibly without the slug.
+            "simkl.com" => match (title_path(&segments), segments.get(1)) {
+                (Some(path), _) => Ok(ExternalId::SimklPath(path)),
+                (None, Some(id)) if is_number(id) => Ok(ExternalId::Simkl(id.to_string())),
+                _ => Err(unsupported()),
+            },
+            _ => Err(unsupported()),
+        }
+    }
+}
+
+/// `/tv/1074318/loki` out of the path segments of a simkl title page url.
+fn title_path(segments: &[&str]) -> Option<String> {
+    match segments {
+        [_, id, _, ..] if is_number(id) => Some(format!("/{}", segments[..3].join("/"))),
+        _ => None,
+    }
+}
+
+fn is_imdb_id(id: &str) -> bool {
+    id.strip_prefix("tt").is_some_and(is_number)
+}
+
+fn is_number(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_digit())
+}
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResolvedId {
+    /// Encoded simkl path, as returned by `search`. `None` when simkl could not be reached.
+    pub id: Option<String>,
+    /// Episode id built without simkl, for `get_episode_server`. Only set when `id` is `None`.
+    pub episode_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ReturnResult {
+    status: bool,
+    message: String,
+    data: ResolvedId,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Arguments {
+    id: String,
+    /// How to read a bare `id`. Inferred when omitted.
+    #[serde(default)]
+    source: Option<IdSource>,
+    #[serde(default, rename = "type")]
+    id_type: Option<IdType>,
+    /// As listed by simkl (`0` for specials), used to build a tv episode id when simkl is unreachable.
+    #[serde(default)]
+    season: Option<usize>,
+    #[serde(default)]
+    episode: Option<usize>,
+}
+
+/// Turn an IMDB, TMDB or simkl id or link into the id `get_episode_list` takes.
+#[unsafe(no_mangle)]
+pub extern "C" fn resolve_id(
+    arguments_ptr : *const c_char,
+) -> *const c_char {
+    ffi::call("Resolve ID", arguments_ptr, run)
+}
+
+/// Non-blocking `resolve_id`: returns a request handle and hands the envelope to `callback` from a worker thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn resolve_id_async(
+    arguments_ptr : *const c_char,
+    callback: Option<ffi::Callback>,
+    user_data: *mut c_void,
+) -> u64 {
+    ffi::call_async("Resolve ID", arguments_ptr, callback, user_data, run)
+}
+
+fn run(args: Arguments, client: &Client) -> Result<ReturnResult> {
+    let external_id = ExternalId::parse(&args.id, args.source, args.id_type)?;
+
+    let numbers = match (args.season, args.episode) {
+        (Some(season), Some(episode)) => Some((season, episode)),
+        (None, None) => None,
+        _ => return Err(Error::InvalidArguments(String::from("`season` and `episode` go together."))),
+    };
+
+    let resolved = resolve_external_id(client, &external_id, numbers)?;
+
+    Ok(ReturnResult {
+        status: true,
+        message: match resolved.id {
+            Some(_) => String::from("Success"),
+            None => String::from("simkl is unreachable, built the episode id from the TMDB id."),
+        },
+        data: resolved,
+    })
+}
+
+
+/// Find the simkl title of `external_id`.
+///
+/// When simkl cannot be reached, a TMDB movie, or a TMDB show with its (season, episode) `numbers`,
+/// still resolves to an episode id, as that is all moviesapi needs.
+pub fn resolve_external_id(client: &Client, external_id: &ExternalId, numbers: Option<(usize, usize)>) -> Result<ResolvedId> {
+    let path = match simkl_path(client, external_id) {
+        Ok(path) => path,
+        Err(e) if is_unreachable(&e) => {
+            let ExternalId::Tmdb { id, id_type } = external_id else {
+                return Err(e);
+            };
+            let numbers = match (id_type, numbers) {
+                (IdType::Movie, _) => None,
+                (IdType::Tv, Some(numbers)) => Some(numbers),
+                (IdType::Tv, None) => return Err(e),
+            };
+
+            let link_id = LinkID {
+                tmdb_id: Some(id.clone()),
+                ..LinkID::default()
+            };
+
+            return Ok(ResolvedId {
+                id: None,
+                episode_id: Some(encode_episode_id(None, id_type.simkl_type(), &link_id, numbers)?),
+            });
+        },
+        Err(e) => return Err(e),
+    };
+
+    Ok(ResolvedId {
+        id: Some(encode(&path).to_string()),
+        episode_id: None,
+    })
+}
+
+/// The canonical simkl path of `external_id`, e.g. `/tv/1074318/loki`.
+pub fn simkl_path(client: &Client, external_id: &ExternalId) -> Result<String> {
+    let settings = client.settings();
+
+    let query = match external_id {
+        ExternalId::SimklPath(path) => return Ok(path.clone()),
+        ExternalId::Imdb(id) => format!("imdb={}", encode(id)),
+        ExternalId::Tmdb { id, id_type } => format!("tmdb={}&type={}", encode(id), id_type.tmdb_type()),
+        ExternalId::Simkl(id) => format!("simkl={}", encode(id)),
+    };
+
+    // simkl redirects to the title page, which names its own path.
+    let url = format!("{}/redirect?to=Simkl&{}", settings.source_base_url, query);
+    let html = client.get(&url, HeaderMap::new())?;
+
+    let canonical = Vis::load(&html).map_err(|e| Error::ParseFailed(e.to_string()))?
+        .find("link[rel=canonical]")
+        .attr("href")
+        .map(|href| href.to_string())
+        .unwrap_or_default();
+
+    let path = Url::parse(&settings.source_base_url)
+        .and_then(|base| base.join(&canonical))
+        .ok()
+        .and_then(|url| title_path(&url.path_segments()?.filter(|segment| !segment.is_empty()).collect::<Vec<_>>()))
+        .ok_or_else(|| Error::LinkIdNotFound(format!("No simkl title for {:?}", external_id)))?;
+
+    // The redirect landed on the title page, so `get_episode_list` can start from it.
+    let _ = client.cache().get_or_fetch(settings, Endpoint::TitlePage, &path, true, || Ok(html));
+
+    Ok(path)
+}
+
+/// Whether simkl could not be reached at all, as opposed to answering something unexpected.
+fn is_unreachable(error: &Error) -> bool {
+    match error {
+        Error::Network(_) | Error::Timeout(_) => true,
+        Error::UpstreamHttp { status, .. } => *status >= 500,
+        Error::RetriesExhausted { last, .. } => is_unreachable(last),
+        _ => false,
+    }
+}
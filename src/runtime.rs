@@ -0,0 +1,232 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::thread;
+
+use crate::config;
+use crate::error::{Error, Result};
+
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// Shared flag telling a call to stop as soon as possible.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+
+static WORKERS: Mutex<Option<Sender<Job>>> = Mutex::new(None);
+
+/// Queue `job` on the worker pool, starting it on first use with `async_workers` threads.
+///
+/// Fails only when not a single worker thread could be started.
+pub(crate) fn spawn(job: Job) -> Result<()> {
+    let mut workers = WORKERS.lock().unwrap_or_else(|e| e.into_inner());
+
+    if workers.is_none() {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let mut started = 0;
+
+        for index in 0..config::settings().async_workers.max(1) {
+            let receiver = receiver.clone();
+            let worker = thread::Builder::new()
+                .name(format!("moviesapi-worker-{}", index))
+                .spawn(move || work(receiver));
+
+            match worker {
+                Ok(_) => started += 1,
+                Err(e) => eprintln!("[Runtime] Failed to start a worker thread: {}", e),
+            }
+        }
+
+        if started == 0 {
+            return Err(Error::Internal(String::from("Failed to start a worker thread.")));
+        }
+
+        *workers = Some(sender);
+    }
+
+    // The receivers live as long as the process, so sending cannot fail.
+    if let Some(sender) = workers.as_ref() {
+        let _ = sender.send(job);
+    }
+
+    Ok(())
+}
+
+fn work(receiver: Arc<Mutex<Receiver<Job>>>) {
+    loop {
+        let job = match receiver.lock().unwrap_or_else(|e| e.into_inner()).recv() {
+            Ok(job) => job,
+            Err(_) => return,
+        };
+
+        job();
+    }
+}
+
+
+/// Cancelled requests are abandoned rather than aborted, and keep a request thread until their own timeout.
+/// This bounds how many can pile up; beyond it, requests wait for a thread to free up.
+pub(crate) const MAX_REQUEST_THREADS: usize = 32;
+
+struct RequestPool {
+    sender: Sender<Job>,
+    receiver: Arc<Mutex<Receiver<Job>>>,
+    threads: usize,
+    idle: Arc<AtomicUsize>,
+}
+
+static REQUESTS: LazyLock<Mutex<RequestPool>> = LazyLock::new(|| {
+    let (sender, receiver) = mpsc::channel::<Job>();
+
+    Mutex::new(RequestPool {
+        sender,
+        receiver: Arc::new(Mutex::new(receiver)),
+        threads: 0,
+        idle: Arc::new(AtomicUsize::new(0)),
+    })
+});
+
+/// Run `job`, a request sent on behalf of a cancellable call, on the request threads.
+///
+/// Threads are started as needed, up to `MAX_REQUEST_THREADS`, and reused afterwards.
+pub(crate) fn spawn_request(job: Job) -> Result<()> {
+    let mut pool = REQUESTS.lock().unwrap_or_else(|e| e.into_inner());
+
+    if pool.idle.load(Ordering::SeqCst) == 0 && pool.threads < MAX_REQUEST_THREADS {
+        let receiver = pool.receiver.clone();
+        let idle = pool.idle.clone();
+        let thread = thread::Builder::new()
+            .name(format!("moviesapi-request-{}", pool.threads))
+            .spawn(move || work_requests(receiver, idle));
+
+        match thread {
+            Ok(_) => pool.threads += 1,
+            Err(e) if pool.threads == 0 => return Err(Error::Internal(format!("Failed to start a request thread: {}", e))),
+            Err(e) => eprintln!("[Runtime] Failed to start a request thread: {}", e),
+        }
+    }
+
+    // The receiver lives as long as the process, so sending cannot fail.
+    let _ = pool.sender.send(job);
+
+    Ok(())
+}
+
+fn work_requests(receiver: Arc<Mutex<Receiver<Job>>>, idle: Arc<AtomicUsize>) {
+    loop {
+        idle.fetch_add(1, Ordering::SeqCst);
+        let job = receiver.lock().unwrap_or_else(|e| e.into_inner()).recv();
+        idle.fetch_sub(1, Ordering::SeqCst);
+
+        match job {
+            Ok(job) => job(),
+            Err(_) => return,
+        }
+    }
+}
+
+/// Number of request threads started so far.
+pub fn request_threads() -> usize {
+    REQUESTS.lock().unwrap_or_else(|e| e.into_inner()).threads
+}
+
+
+/// Reports `CANCELLED` to the host of a call cancelled before a worker picked it up.
+pub(crate) type ReportCancelled = Box<dyn FnOnce() + Send + 'static>;
+
+struct Call {
+    token: CancelToken,
+    /// Set while the call is queued: taken by `cancel`, or dropped by `start` once a worker runs it.
+    report_cancelled: Option<ReportCancelled>,
+}
+
+static NEXT_HANDLE: AtomicU64 = AtomicU64::new(1);
+static HANDLES: LazyLock<Mutex<HashMap<u64, Call>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Allocate a handle for a new call. Handles are never `0`.
+pub(crate) fn register(report_cancelled: ReportCancelled) -> (u64, CancelToken) {
+    let handle = NEXT_HANDLE.fetch_add(1, Ordering::SeqCst);
+    let token = CancelToken::new();
+
+    HANDLES.lock().unwrap_or_else(|e| e.into_inner()).insert(handle, Call {
+        token: token.clone(),
+        report_cancelled: Some(report_cancelled),
+    });
+
+    (handle, token)
+}
+
+/// Called by the worker about to run `handle`. Returns `false` if it was cancelled while queued and already reported.
+pub(crate) fn start(handle: u64) -> bool {
+    match HANDLES.lock().unwrap_or_else(|e| e.into_inner()).get_mut(&handle) {
+        Some(call) => {
+            call.report_cancelled = None;
+            true
+        },
+        None => false,
+    }
+}
+
+/// Forget `handle` once its callback has been called.
+pub(crate) fn release(handle: u64) {
+    HANDLES.lock().unwrap_or_else(|e| e.into_inner()).remove(&handle);
+}
+
+/// Cancel the call behind `handle`. Returns `false` if it already finished or never existed.
+///
+/// A call still waiting for a worker is reported right away, from a thread of its own, and never runs.
+pub fn cancel(handle: u64) -> bool {
+    let mut handles = HANDLES.lock().unwrap_or_else(|e| e.into_inner());
+
+    let Some(call) = handles.get_mut(&handle) else {
+        return false;
+    };
+    call.token.cancel();
+
+    let Some(report_cancelled) = call.report_cancelled.take() else {
+        return true;
+    };
+    handles.remove(&handle);
+    drop(handles);
+
+    // The host may call `cancel_request` while holding what its callback needs, so never call back from here.
+    let (sender, receiver) = mpsc::channel::<ReportCancelled>();
+    let reporter = thread::Builder::new()
+        .name(String::from("moviesapi-cancel"))
+        .spawn(move || {
+            if let Ok(report_cancelled) = receiver.recv() {
+                report_cancelled();
+            }
+        });
+
+    match reporter {
+        Ok(_) => {
+            let _ = sender.send(report_cancelled);
+        },
+        Err(e) => {
+            eprintln!("[Runtime] Failed to start a thread, reporting the cancel inline: {}", e);
+            report_cancelled();
+        },
+    }
+
+    true
+}
@@ -1,26 +1,38 @@
-use std::ffi::{CString, CStr};
+use std::ffi::c_void;
 use std::os::raw::{c_char};
-use std::panic;
-use std::ptr;
 
 
 use serde::{Deserialize, Serialize};
-use serde_json::{ from_str, Value};
-use reqwest::header::{HeaderMap, HOST, ORIGIN, REFERER, HeaderValue, USER_AGENT};
+use serde_json::{from_str, Value};
+use reqwest::header::{HeaderMap, HOST, ORIGIN, REFERER};
 use urlencoding::{encode};
 use html_escape::decode_html_entities;
 
-use crate::{SOURCE_HOST, SOURCE_ORIGIN, SOURCE_REFERER};
+use crate::cache::{normalize_key, Endpoint};
+use crate::client::Client;
+use crate::error::{Error, Result};
+use crate::utils::ffi;
+use crate::utils::http::header_value;
 
 
 
 
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Data{
-    id: String,
-    title: String,
-    cover: String
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Data{
+    pub id: String,
+    pub title: String,
+    pub cover: String,
+    /// The catalogue the item was found in.
+    #[serde(rename = "type")]
+    pub media_type: MediaType,
+    pub year: Option<u32>,
+    /// Original and localised titles other than `title`.
+    pub alt_titles: Vec<String>,
+    /// Simkl rating out of 10, or the IMDB one when simkl has none.
+    pub rating: Option<f64>,
+    /// Minutes per episode, or of the whole movie.
+    pub runtime: Option<u32>
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -28,11 +40,166 @@ struct ReturnResult {
     status: bool,
     message: String,
     data: Vec<Data>,
+    paging: Paging,
 }
 
 #[derive(Serialize, Deserialize)]
 struct Arguments {
-    search: String
+    #[serde(flatten)]
+    query: SearchQuery,
+    /// Skip the response cache and fetch a fresh result.
+    #[serde(default)]
+    bypass_cache: bool,
+}
+
+/// Which simkl catalogues a search goes through.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaType {
+    Movie,
+    Tv,
+    /// Only searched when asked for.
+    Anime,
+    /// Movies and tv shows, as `search` always did.
+    #[default]
+    All,
+}
+
+impl MediaType {
+    /// The catalogues searched, one request each, in the order their results are listed.
+    pub fn passes(&self) -> &'static [MediaType] {
+        match self {
+            MediaType::Movie => &[MediaType::Movie],
+            MediaType::Tv => &[MediaType::Tv],
+            MediaType::Anime => &[MediaType::Anime],
+            MediaType::All => &[MediaType::Movie, MediaType::Tv],
+        }
+    }
+
+    /// Value of the `type` parameter of `search.php`.
+    fn search_type(&self) -> &'static str {
+        match self {
+            MediaType::Movie => "movies",
+            MediaType::Tv => "tv",
+            MediaType::Anime => "anime",
+            MediaType::All => "all",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    /// As ranked by simkl, catalogue by catalogue.
+    #[default]
+    Relevance,
+    YearDesc,
+    YearAsc,
+    Title,
+}
+
+/// Inclusive release year bounds. Items without a year never match a range.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct YearRange {
+    pub from: Option<u32>,
+    pub to: Option<u32>,
+}
+
+impl YearRange {
+    fn contains(&self, year: Option<u32>) -> bool {
+        let Some(year) = year else {
+            return false;
+        };
+
+        self.from.is_none_or(|from| year >= from) && self.to.is_none_or(|to| year <= to)
+    }
+}
+
+/// Filters, order and page of a `search` call.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchQuery {
+    pub search: String,
+    /// 1-based.
+    #[serde(default = "first_page")]
+    pub page: usize,
+    /// Omitted to return every result on a single page.
+    #[serde(default)]
+    pub per_page: Option<usize>,
+    #[serde(default, rename = "type")]
+    pub media_type: MediaType,
+    #[serde(default)]
+    pub year: Option<YearRange>,
+    #[serde(default)]
+    pub sort: SortOrder,
+}
+
+fn first_page() -> usize {
+    1
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Paging {
+    pub page: usize,
+    pub per_page: Option<usize>,
+    /// Number of results matching the filters, across every page.
+    pub total: Option<usize>,
+    pub has_next: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchPage {
+    pub data: Vec<Data>,
+    pub paging: Paging,
+}
+
+impl SearchQuery {
+    pub fn new(search: &str) -> Self {
+        SearchQuery {
+            search: search.to_string(),
+            page: first_page(),
+            per_page: None,
+            media_type: MediaType::default(),
+            year: None,
+            sort: SortOrder::default(),
+        }
+    }
+
+    /// Filter, sort and cut the unfiltered results of `search_titles` down to the requested page.
+    pub fn apply(&self, mut items: Vec<Data>) -> Result<SearchPage> {
+        if self.page == 0 {
+            return Err(Error::InvalidArguments(String::from("`page` starts at 1.")));
+        }
+        if self.per_page == Some(0) {
+            return Err(Error::InvalidArguments(String::from("`per_page` must be at least 1.")));
+        }
+
+        if let Some(year) = self.year {
+            items.retain(|item| year.contains(item.year));
+        }
+
+        // Stable sorts keep the simkl ranking between equal items.
+        match self.sort {
+            SortOrder::Relevance => {},
+            SortOrder::YearDesc => items.sort_by_key(|item| std::cmp::Reverse(item.year)),
+            SortOrder::YearAsc => items.sort_by_key(|item| item.year.unwrap_or(u32::MAX)),
+            SortOrder::Title => items.sort_by_cached_key(|item| item.title.to_lowercase()),
+        }
+
+        let total = items.len();
+        let per_page = self.per_page.unwrap_or(total.max(1));
+        let start = (self.page - 1).saturating_mul(per_page).min(total);
+        let end = start.saturating_add(per_page).min(total);
+
+        Ok(SearchPage {
+            data: items.drain(start..end).collect(),
+            paging: Paging {
+                page: self.page,
+                per_page: self.per_page,
+                total: Some(total),
+                has_next: end < total,
+            },
+        })
+    }
 }
 
 
@@ -40,102 +207,137 @@ struct Arguments {
 pub extern "C" fn search(
     arguments_ptr : *const c_char,
 ) -> *const c_char {
-    let result = panic::catch_unwind(|| {
-        let mut return_result = ReturnResult {
-            status: false,
-            message: String::from(""),
-            data: Vec::new(),
-        };
+    ffi::call("Search", arguments_ptr, run)
+}
 
-        // Check argument before processing
-        if arguments_ptr.is_null() {
-            panic!("Expected 1 argument.");
-        }
+/// Non-blocking `search`: returns a request handle and hands the envelope to `callback` from a worker thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn search_async(
+    arguments_ptr : *const c_char,
+    callback: Option<ffi::Callback>,
+    user_data: *mut c_void,
+) -> u64 {
+    ffi::call_async("Search", arguments_ptr, callback, user_data, run)
+}
 
-        let args: Arguments = unsafe { 
-            from_str(&CStr::from_ptr(arguments_ptr as *mut c_char).to_string_lossy().into_owned()).unwrap()
-        };
-        
-        // ================================================
+fn run(args: Arguments, client: &Client) -> Result<ReturnResult> {
+    let query = args.query;
 
+    // Every page and filter of the same search shares one cached, unfiltered result.
+    let key = format!("{}:{}", query.media_type.search_type(), normalize_key(&query.search));
+    let items = client.cache().get_or_fetch(client.settings(), Endpoint::Search, &key, args.bypass_cache, || {
+        search_titles(client, &query.search, query.media_type)
+    })?;
 
-        let search_string = args.search;
+    let page = query.apply(items)?;
 
-        let client = reqwest::blocking::Client::new();
-        let mut headers = HeaderMap::new();
+    Ok(ReturnResult {
+        status: true,
+        message: String::from("Success"),
+        data: page.data,
+        paging: page.paging,
+    })
+}
 
-        headers.insert(USER_AGENT, HeaderValue::from_static(
-            "Chrome/126.0.0.0"
-        ));
 
-        headers.insert(HOST, HeaderValue::from_str(SOURCE_HOST).unwrap());
-        headers.insert(REFERER, HeaderValue::from_str(SOURCE_REFERER).unwrap());
-        headers.insert(ORIGIN, HeaderValue::from_str(SOURCE_ORIGIN).unwrap());
+/// Search the simkl catalogues of `media_type` for titles matching `search_string`, unfiltered and unpaged.
+pub fn search_titles(client: &Client, search_string: &str, media_type: MediaType) -> Result<Vec<Data>> {
+    let settings = client.settings();
+    let mut headers = HeaderMap::new();
 
-        let mut new_data: Vec<Data> = Vec::new();
+    headers.insert(HOST, header_value(&settings.source_host())?);
+    headers.insert(REFERER, header_value(&settings.source_referer)?);
+    headers.insert(ORIGIN, header_value(&settings.source_origin)?);
 
+    let mut new_data: Vec<Data> = Vec::new();
 
-        for search_type in ["movies", "tv"] {
-            let url = format!("https://{}/ajax/full/search.php?s={}&type={}&sort=0", 
-                SOURCE_HOST, 
-                if search_string.trim().is_empty() { "+".to_string() } else { encode(&search_string).to_string() },
-                search_type,
-            );
 
-            let res = client.post(&url)
-                .headers(headers.clone())
-                .send()
-                .unwrap();
+    for pass in media_type.passes() {
+        let url = format!("{}/ajax/full/search.php?s={}&type={}&sort=0",
+            settings.source_base_url,
+            if search_string.trim().is_empty() { "+".to_string() } else { encode(search_string).to_string() },
+            pass.search_type(),
+        );
 
+        let body = client.post(&url, headers.clone(), None)?;
 
-            if !res.status().is_success(){
-                panic!("Error: {}", res.status());
-            }
+        /* Do the work here */
+        let data: Value = from_str(&body).map_err(|e| Error::ParseFailed(e.to_string()))?;
+
+        // An empty result set is encoded as `[]` instead of `{}`.
+        let items = match &data {
+            Value::Object(items) => items,
+            Value::Array(items) if items.is_empty() => continue,
+            _ => return Err(Error::ParseFailed(String::from("Unexpected search response."))),
+        };
+
+        for value in items.values() {
+
+            let title = value.get("titles")
+                .and_then(|v| v.get("m"))
+                .and_then(|v| v.as_str())
+                .unwrap_or_default();
+
+            let poster = value.get("poster")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| Error::ParseFailed(String::from("Search item has no `poster`.")))?;
+
+            let cover: String = format!("{}/{}_m.webp", settings.poster_base_url, poster);
+
+            let raw_id = value.get("url")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| Error::ParseFailed(String::from("Search item has no `url`.")))?;
 
-            /* Do the work here */
-            let data:Value = res.json().unwrap();
-            
-            for (_, value) in data.as_object().unwrap().into_iter() {
-                
-                let title: String = value.get("titles")
-                    .and_then(|v| v.get("m"))
-                    .unwrap_or(&Value::String("".to_string()))
-                    .as_str().unwrap().to_string();
-
-                let cover: String = format!("https://simkl.in/posters/{}_m.webp",
-                    value.get("poster").unwrap().as_str().unwrap().to_string()
-                );
-
-                let raw_id: String = value.get("url").unwrap().as_str().unwrap().to_string();
-
-                let id = encode(&raw_id).to_string();
-
-                new_data.push(Data {
-                    id: id,
-                    title: decode_html_entities(&title).to_string(),
-                    cover: cover
-                });
+            let id = encode(raw_id).to_string();
+
+            let title = decode_html_entities(title).to_string();
+
+            let year = value.get("year")
+                .and_then(|v| v.as_u64())
+                .and_then(|v| u32::try_from(v).ok());
+
+            let mut alt_titles: Vec<String> = Vec::new();
+            if let Some(titles) = value.get("titles").and_then(|v| v.as_object()) {
+                for (key, alt_title) in titles {
+                    let Some(alt_title) = alt_title.as_str().filter(|_| key != "m") else {
+                        continue;
+                    };
+
+                    let alt_title = decode_html_entities(alt_title).to_string();
+                    if !alt_title.is_empty() && alt_title != title && !alt_titles.contains(&alt_title) {
+                        alt_titles.push(alt_title);
+                    }
+                }
             }
-            
-            /* --- */
+
+            let rating = ["simkl", "imdb"].iter()
+                .find_map(|source| value.get("ratings")
+                    .and_then(|v| v.get(source))
+                    .and_then(|v| v.get("rating"))
+                    .and_then(|v| v.as_f64()));
+
+            // Sent as a string, sometimes as a number.
+            let runtime = value.get("runtime")
+                .and_then(|v| match v {
+                    Value::String(runtime) => runtime.trim().parse::<u32>().ok(),
+                    _ => v.as_u64().and_then(|v| u32::try_from(v).ok()),
+                })
+                .filter(|runtime| *runtime > 0);
+
+            new_data.push(Data {
+                id,
+                title,
+                cover,
+                media_type: *pass,
+                year,
+                alt_titles,
+                rating,
+                runtime
+            });
         }
 
-        return_result.status = true;
-        return_result.message = String::from("Success");
-        return_result.data = new_data;
-        
-        return serde_json::to_string(&return_result).unwrap();
-        
-    });
-
-    match result {
-        Ok(data) => {
-            let result = CString::new(data).unwrap();
-            return result.into_raw();
-        },
-        Err(e) => {
-            eprintln!("[Search] Error: {:?}", e);
-            return ptr::null();
-        },
+        /* --- */
     }
-}
\ No newline at end of file
+
+    Ok(new_data)
+}
@@ -0,0 +1,54 @@
+use std::os::raw::{c_char};
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::config::{self, Settings};
+use crate::error::{Error, Result};
+use crate::utils::ffi;
+
+
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ReturnResult {
+    status: bool,
+    message: String,
+    data: Settings,
+}
+
+
+/// Replace the library settings. Omitted fields are reset to their defaults, so `{}` restores them all.
+#[unsafe(no_mangle)]
+pub extern "C" fn set_config(
+    arguments_ptr : *const c_char,
+) -> *const c_char {
+    ffi::respond("Set Config", || {
+        // Check argument before processing
+        let settings: Settings = ffi::parse_arguments(arguments_ptr)?;
+
+        // ================================================
+
+        validate(&settings)?;
+        config::set_settings(settings.clone());
+
+        Ok(ReturnResult {
+            status: true,
+            message: String::from("Success"),
+            data: settings,
+        })
+    })
+}
+
+
+fn validate(settings: &Settings) -> Result<()> {
+    for (name, url) in [
+        ("source_base_url", &settings.source_base_url),
+        ("poster_base_url", &settings.poster_base_url),
+        ("server_base_url", &settings.server_base_url),
+        ("scrapify_url", &settings.scrapify_url),
+    ] {
+        Url::parse(url).map_err(|e| Error::InvalidArguments(format!("`{}`: {}", name, e)))?;
+    }
+
+    Ok(())
+}
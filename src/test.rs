@@ -77,4 +77,1543 @@ mod tests {
             free_ptr(get_server_ptr as *mut c_char);
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_invalid_arguments() {
+        use std::ptr;
+        use serde_json::{from_str, Value};
+        use crate::search::search;
+        unsafe {
+            let search_ptr = search(ptr::null());
+            assert!(!search_ptr.is_null());
+            let result: Value = from_str(CStr::from_ptr(search_ptr).to_str().unwrap()).unwrap();
+            assert_eq!(result["status"], false);
+            assert_eq!(result["error"]["code"], "INVALID_ARGUMENTS");
+            free_ptr(search_ptr as *mut c_char);
+        }
+    }
+
+    /// `user_data` owns a boxed `Sender<String>`, released here once the result is forwarded.
+    extern "C" fn send_result(result: *const c_char, user_data: *mut std::ffi::c_void) {
+        unsafe {
+            let sender = Box::from_raw(user_data as *mut std::sync::mpsc::Sender<String>);
+            sender.send(CStr::from_ptr(result).to_str().unwrap().to_owned()).unwrap();
+            free_ptr(result as *mut c_char);
+        }
+    }
+
+    fn boxed_sender(sender: &std::sync::mpsc::Sender<String>) -> *mut std::ffi::c_void {
+        Box::into_raw(Box::new(sender.clone())) as *mut std::ffi::c_void
+    }
+
+    #[test]
+    fn test_get_episode_server_async() {
+        use std::sync::mpsc;
+        use std::time::Duration;
+        use serde_json::{from_str, Value};
+        use crate::get_episode_server::get_episode_server_async;
+
+        let (sender, receiver) = mpsc::channel::<String>();
+        let args = CString::new(to_string(&json!({
+            "episode_id": "%7B%22e%22%3A0%2C%22id_type%22%3A%22tv%22%2C%22imdb_id%22%3A%22tt9140554%22%2C%22s%22%3A0%2C%22tmdb_id%22%3A%2284958%22%7D",
+        })).unwrap()).expect("CString::new failed");
+
+        let handle = get_episode_server_async(args.as_ptr(), Some(send_result), boxed_sender(&sender));
+        assert_ne!(handle, 0);
+        drop(args);
+
+        let result: Value = from_str(&receiver.recv_timeout(Duration::from_secs(10)).unwrap()).unwrap();
+        assert_eq!(result["status"], true);
+        assert_eq!(result["data"]["SERVER"][0]["title"], "SFlix2");
+
+        let handle = get_episode_server_async(std::ptr::null(), Some(send_result), boxed_sender(&sender));
+        let result: Value = from_str(&receiver.recv_timeout(Duration::from_secs(10)).unwrap()).unwrap();
+        assert_eq!(result["error"]["code"], "INVALID_ARGUMENTS");
+
+        // The call is over, so there is nothing left to cancel.
+        assert!(!crate::cancel_request::cancel_request(handle));
+    }
+
+    #[test]
+    fn test_set_config_invalid_url() {
+        use serde_json::{from_str, Value};
+        use crate::set_config::set_config;
+        unsafe {
+            let args = CString::new(to_string(&json!({
+                "source_base_url": "not a url",
+            })).unwrap()).expect("CString::new failed");
+
+            let set_config_ptr = set_config(args.as_ptr());
+            let result: Value = from_str(CStr::from_ptr(set_config_ptr).to_str().unwrap()).unwrap();
+            assert_eq!(result["status"], false);
+            assert_eq!(result["error"]["code"], "INVALID_ARGUMENTS");
+            free_ptr(set_config_ptr as *mut c_char);
+        }
+    }
+}
+
+/// Offline tests replaying recorded upstream responses from `tests/fixtures`.
+mod offline {
+    use std::collections::{HashMap, VecDeque};
+    use std::fs;
+    use std::sync::{Arc, Mutex};
+
+    use reqwest::Method;
+    use urlencoding::decode;
+
+    use crate::client::{Client, Request, Response, Transport};
+    use crate::config::Settings;
+    use crate::error::{Error, Result};
+
+
+    const LOKI_ID: &str = "%2Ftv%2F1074318%2Floki";
+    const LOKI_IN_TRAINING_ID: &str = "%2Fmovies%2F1418962%2Flego-marvel-avengers-loki-in-training";
+    const SCRAPIFY_URL: &str = "https://ww2.moviesapi.to/api/scrapify/v1/fetch";
+    /// `ENCRYPTION_KEY` of `moviesapi_module.js`.
+    const ENCRYPTION_KEY: &str = "b7f1c9d2e4a64f0d8e3c5a1b9d7e2f60";
+
+    type Routes = HashMap<(Method, String), VecDeque<Reply>>;
+
+    #[derive(Clone)]
+    enum Reply {
+        Status(u16, String),
+        /// The request runs out of time before any response.
+        Timeout,
+    }
+
+    /// Serves fixture files by method and url, recording every request it sees.
+    ///
+    /// Several responses registered for the same route are served in order, the last one repeating.
+    #[derive(Clone, Default)]
+    pub(crate) struct Fixtures {
+        routes: Arc<Mutex<Routes>>,
+        pub(crate) requests: Arc<Mutex<Vec<Request>>>,
+    }
+
+    impl Fixtures {
+        pub(crate) fn new() -> Self {
+            Self::default()
+        }
+
+        pub(crate) fn route(self, method: Method, url: &str, fixture: &str) -> Self {
+            self.push(method, url, Reply::Status(200, read_fixture(fixture)))
+        }
+
+        pub(crate) fn status(self, method: Method, url: &str, status: u16) -> Self {
+            self.push(method, url, Reply::Status(status, String::new()))
+        }
+
+        /// Answer scrapify with `fixture` when asked for mirror `srv` of `source`, ahead of what `SCRAPIFY_URL` is routed to.
+        pub(crate) fn scrapify(self, source: &str, srv: usize, fixture: &str) -> Self {
+            self.route(Method::POST, &scrapify_route(source, srv), fixture)
+        }
+
+        /// Fail the request with `Error::Timeout`, as if `url` never answered.
+        pub(crate) fn timeout(self, method: Method, url: &str) -> Self {
+            self.push(method, url, Reply::Timeout)
+        }
+
+        /// Serve `fixture` instead of whatever `url` was routed to so far.
+        pub(crate) fn reroute(self, method: Method, url: &str, fixture: &str) -> Self {
+            self.clear(method.clone(), url).route(method, url, fixture)
+        }
+
+        /// Answer `url` with `status` instead of whatever it was routed to so far.
+        pub(crate) fn reroute_status(self, method: Method, url: &str, status: u16) -> Self {
+            self.clear(method.clone(), url).status(method, url, status)
+        }
+
+        fn clear(self, method: Method, url: &str) -> Self {
+            self.routes.lock().unwrap().remove(&(method, url.to_string()));
+            self
+        }
+
+        fn push(self, method: Method, url: &str, reply: Reply) -> Self {
+            self.routes.lock().unwrap()
+                .entry((method, url.to_string()))
+                .or_default()
+                .push_back(reply);
+            self
+        }
+
+        /// Every upstream page the scrapers need for Loki and one movie.
+        pub(crate) fn simkl() -> Self {
+            Self::new()
+                .route(Method::POST, "https://simkl.com/ajax/full/search.php?s=loki&type=movies&sort=0", "simkl_search_movies.json")
+                .route(Method::POST, "https://simkl.com/ajax/full/search.php?s=loki&type=tv&sort=0", "simkl_search_tv.json")
+                .route(Method::POST, "https://simkl.com/ajax/full/search.php?s=loki&type=anime&sort=0", "simkl_search_anime.json")
+                .route(Method::GET, "https://simkl.com/tv/1074318/loki", "simkl_tv_loki.html")
+                .route(Method::GET, "https://simkl.com/tv/1074318/loki/episodes", "simkl_tv_loki_episodes.html")
+                .route(Method::GET, "https://simkl.com/movies/1418962/lego-marvel-avengers-loki-in-training", "simkl_movie_loki_in_training.html")
+                .route(Method::GET, "https://simkl.com/movies/1418962/lego-marvel-avengers-loki-in-training/episodes", "simkl_movie_loki_in_training.html")
+                .route(Method::GET, "https://ww2.moviesapi.to/tv/84958/1/1", "moviesapi_tv.html")
+                .route(Method::GET, "https://ww2.moviesapi.to/assets/index-4f2a9c1e.js", "moviesapi_module.js")
+                .route(Method::POST, SCRAPIFY_URL, "scrapify_fetch.json")
+        }
+
+        /// A client on default settings, without waiting between retries.
+        pub(crate) fn client(&self) -> Client {
+            Client::with_transport(self.clone()).with_settings(Settings {
+                retry_base_delay_ms: 0,
+                retry_max_delay_ms: 0,
+                ..Settings::default()
+            })
+        }
+
+        pub(crate) fn requested(&self, url: &str) -> usize {
+            self.requests.lock().unwrap().iter().filter(|req| req.url == url).count()
+        }
+    }
+
+    impl Transport for Fixtures {
+        fn send(&self, request: Request) -> Result<Response> {
+            let mut routes = self.routes.lock().unwrap();
+            let url = scrapify_payload(&request)
+                .map(|(source, srv)| scrapify_route(&source, srv))
+                .filter(|route| routes.contains_key(&(Method::POST, route.clone())))
+                .unwrap_or_else(|| request.url.clone());
+
+            let route = routes
+                .get_mut(&(request.method.clone(), url))
+                .and_then(|responses| match responses.len() {
+                    1 => responses.front().cloned(),
+                    _ => responses.pop_front(),
+                });
+            drop(routes);
+            let url = request.url.clone();
+            self.requests.lock().unwrap().push(request);
+
+            match route {
+                Some(Reply::Status(status, body)) => Ok(Response { status, body }),
+                Some(Reply::Timeout) => Err(Error::Timeout(format!("No response from {}", url))),
+                None => Err(Error::Network(format!("No fixture for {}", url))),
+            }
+        }
+    }
+
+    /// Where `Fixtures::scrapify` routes the mirror `srv` of `source`.
+    fn scrapify_route(source: &str, srv: usize) -> String {
+        format!("{}#{}/{}", SCRAPIFY_URL, source, srv)
+    }
+
+    /// The source and mirror a request to scrapify asks for, read out of its encrypted payload.
+    fn scrapify_payload(request: &Request) -> Option<(String, usize)> {
+        if request.url != SCRAPIFY_URL {
+            return None;
+        }
+
+        let encrypted = request.body.as_ref()?.get("payload")?.as_str()?;
+        let payload: serde_json::Value = serde_json::from_str(&crate::utils::aes::decrypt(encrypted, ENCRYPTION_KEY).ok()?).ok()?;
+
+        Some((payload.get("source")?.as_str()?.to_string(), payload.get("srv")?.as_str()?.parse().ok()?))
+    }
+
+    pub(crate) fn read_fixture(name: &str) -> String {
+        fs::read_to_string(format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name))
+            .unwrap_or_else(|e| panic!("Missing fixture {}: {}", name, e))
+    }
+
+    fn loki_episode_info() -> crate::get_server::EpisodeInfo {
+        crate::get_server::EpisodeInfo {
+            title_id: Some(String::from("/tv/1074318/loki")),
+            id_type: String::from("tv"),
+            imdb_id: String::from("tt9140554"),
+            tmdb_id: String::from("84958"),
+            ids: Default::default(),
+            provider: String::from("moviesapi"),
+            source_id: String::from("sflix2"),
+            srv: 0,
+            season: Some(1),
+            episode: Some(1),
+            s: None,
+            e: None,
+        }
+    }
+
+
+    #[test]
+    fn test_search() {
+        use crate::search::{search_titles, MediaType};
+
+        let fixtures = Fixtures::simkl();
+        let data = search_titles(&fixtures.client(), "loki", MediaType::All).unwrap();
+
+        assert_eq!(data.len(), 3);
+        // `all` keeps to the movies and tv catalogues; anime is opt-in.
+        assert_eq!(fixtures.requested("https://simkl.com/ajax/full/search.php?s=loki&type=movies&sort=0"), 1);
+        assert_eq!(fixtures.requested("https://simkl.com/ajax/full/search.php?s=loki&type=tv&sort=0"), 1);
+        assert_eq!(fixtures.requested("https://simkl.com/ajax/full/search.php?s=loki&type=anime&sort=0"), 0);
+        assert_eq!(data[0].id, LOKI_IN_TRAINING_ID);
+        assert_eq!(data[1].id, LOKI_ID);
+        assert_eq!(data[1].title, "Loki");
+        assert_eq!(data[1].cover, "https://simkl.in/posters/16/1634585263b1c6da2f_m.webp");
+        assert_eq!(data[2].title, "Loki & Friends");
+    }
+
+    #[test]
+    fn test_search_item_details() {
+        use crate::search::{search_titles, MediaType};
+
+        let data = search_titles(&Fixtures::simkl().client(), "loki", MediaType::All).unwrap();
+
+        assert_eq!(data[0].media_type, MediaType::Movie);
+        assert_eq!(data[0].rating, Some(6.2));
+        assert_eq!(data[1].media_type, MediaType::Tv);
+        assert_eq!(data[1].year, Some(2021));
+        assert_eq!(data[1].alt_titles, ["Marvel's Loki"]);
+        assert_eq!(data[1].rating, Some(8.1));
+        assert_eq!(data[1].runtime, Some(50));
+        assert!(data[2].alt_titles.is_empty());
+    }
+
+    #[test]
+    fn test_search_upstream_error() {
+        use crate::search::{search_titles, MediaType};
+
+        let fixtures = Fixtures::new()
+            .status(Method::POST, "https://simkl.com/ajax/full/search.php?s=loki&type=movies&sort=0", 404);
+
+        match search_titles(&fixtures.client(), "loki", MediaType::All) {
+            Err(Error::UpstreamHttp { status, .. }) => assert_eq!(status, 404),
+            other => panic!("Expected UpstreamHttp, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_search_single_type() {
+        use crate::search::{search_titles, MediaType};
+
+        let fixtures = Fixtures::simkl();
+        let data = search_titles(&fixtures.client(), "loki", MediaType::Tv).unwrap();
+
+        assert_eq!(data.len(), 2);
+        assert_eq!(fixtures.requested("https://simkl.com/ajax/full/search.php?s=loki&type=movies&sort=0"), 0);
+        assert_eq!(fixtures.requested("https://simkl.com/ajax/full/search.php?s=loki&type=anime&sort=0"), 0);
+    }
+
+    #[test]
+    fn test_search_query() {
+        use crate::search::{search_titles, MediaType, SearchQuery, SortOrder, YearRange};
+
+        let items = search_titles(&Fixtures::simkl().client(), "loki", MediaType::All).unwrap();
+
+        let page = SearchQuery {
+            per_page: Some(2),
+            sort: SortOrder::YearDesc,
+            ..SearchQuery::new("loki")
+        }.apply(items.clone()).unwrap();
+        assert_eq!(page.data.iter().map(|item| item.year).collect::<Vec<_>>(), [Some(2023), Some(2022)]);
+        assert_eq!(page.paging.total, Some(3));
+        assert!(page.paging.has_next);
+
+        let page = SearchQuery {
+            page: 2,
+            per_page: Some(2),
+            sort: SortOrder::YearDesc,
+            ..SearchQuery::new("loki")
+        }.apply(items.clone()).unwrap();
+        assert_eq!(page.data.len(), 1);
+        assert_eq!(page.data[0].title, "Loki");
+        assert!(!page.paging.has_next);
+
+        let page = SearchQuery {
+            year: Some(YearRange { from: Some(2022), to: None }),
+            ..SearchQuery::new("loki")
+        }.apply(items.clone()).unwrap();
+        assert_eq!(page.paging.total, Some(2));
+        assert!(!page.paging.has_next);
+
+        assert!(matches!(SearchQuery { page: 0, ..SearchQuery::new("loki") }.apply(items), Err(Error::InvalidArguments(_))));
+    }
+
+    #[test]
+    fn test_get_link_id() {
+        use crate::get_episode_list::get_link_id;
+
+        let link_id = get_link_id(&Fixtures::simkl().client(), "/tv/1074318/loki").unwrap();
+
+        assert_eq!(link_id.simkl_id.as_deref(), Some("1074318"));
+        assert_eq!(link_id.imdb_id.as_deref(), Some("tt9140554"));
+        assert_eq!(link_id.tmdb_id.as_deref(), Some("84958"));
+        assert_eq!(link_id.tvdb_id.as_deref(), Some("362472"));
+        assert_eq!(link_id.trakt_id.as_deref(), Some("loki"));
+        assert_eq!(link_id.mal_id, None);
+    }
+
+    #[test]
+    fn test_get_link_id_without_tmdb() {
+        use crate::get_episode_list::{episode_list, get_link_id};
+
+        let fixtures = Fixtures::new()
+            .route(Method::GET, "https://simkl.com/anime/2209483/frieren", "simkl_anime_frieren.html");
+
+        let link_id = get_link_id(&fixtures.client(), "/anime/2209483/frieren").unwrap();
+        assert_eq!(link_id.mal_id.as_deref(), Some("52991"));
+        assert_eq!(link_id.anilist_id.as_deref(), Some("154587"));
+        assert_eq!(link_id.others["anidb"], "17617");
+        assert_eq!(link_id.tmdb_id, None);
+
+        // No registered provider can look the title up without a TMDB id.
+        match episode_list(&fixtures.client(), "%2Fanime%2F2209483%2Ffrieren") {
+            Err(Error::LinkIdNotFound(message)) => assert_eq!(message, "TMDB ID not found."),
+            result => panic!("Expected LinkIdNotFound, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_anime_series() {
+        use crate::get_episode_list::episode_list;
+        use crate::get_episode_server::episode_servers;
+        use crate::get_server::{resolve_server, EpisodeInfo};
+        use crate::search::{search_titles, MediaType};
+
+        let fixtures = Fixtures::simkl()
+            .route(Method::POST, "https://simkl.com/ajax/full/search.php?s=ragnarok&type=anime&sort=0", "simkl_search_anime_ragnarok.json")
+            .route(Method::GET, "https://simkl.com/anime/38922/mythical-detective-loki-ragnarok", "simkl_anime_loki_ragnarok.html")
+            .route(Method::GET, "https://simkl.com/anime/38922/mythical-detective-loki-ragnarok/episodes", "simkl_anime_loki_ragnarok_episodes.html")
+            .route(Method::GET, "https://ww2.moviesapi.to/tv/31724/1/2", "moviesapi_tv.html");
+        let client = fixtures.client();
+
+        let items = search_titles(&client, "ragnarok", MediaType::Anime).unwrap();
+        assert_eq!(items[0].media_type, MediaType::Anime);
+
+        // An anime series lists its episodes instead of playing whole like a movie.
+        let seasons = seasons(episode_list(&client, &items[0].id).unwrap());
+        assert_eq!(seasons[0].episodes.len(), 2);
+
+        let servers = episode_servers(&seasons[0].episodes[1].id).unwrap();
+        let episode_info = EpisodeInfo::decode(&servers["SERVER"][0].id).unwrap();
+        assert_eq!(episode_info.id_type, "anime");
+        assert_eq!(episode_info.tmdb_id, "31724");
+        assert_eq!(episode_info.numbers(), Some((1, 2)));
+
+        resolve_server(&client, &episode_info).unwrap();
+        assert_eq!(fixtures.requested("https://ww2.moviesapi.to/tv/31724/1/2"), 1);
+    }
+
+    #[test]
+    fn test_unnumbered_season() {
+        use crate::get_episode_list::{episode_list_slice, season_list, EpisodeFilter};
+        use crate::get_server::EpisodeInfo;
+
+        let id = "%2Fanime%2F38922%2Fmythical-detective-loki-ragnarok";
+        let fixtures = Fixtures::new()
+            .route(Method::GET, "https://simkl.com/anime/38922/mythical-detective-loki-ragnarok", "simkl_anime_loki_ragnarok.html")
+            .route(Method::GET, "https://simkl.com/anime/38922/mythical-detective-loki-ragnarok/episodes", "simkl_anime_loki_ragnarok_episodes_unnumbered.html");
+        let client = fixtures.client();
+
+        // A heading without a number is reported as the season its episodes are filtered and addressed by.
+        let filter = EpisodeFilter { season: Some(1), ..Default::default() };
+        let seasons = seasons(episode_list_slice(&client, id, &filter).unwrap());
+        assert_eq!(seasons[0].number, Some(1));
+
+        let episode = &seasons[0].episodes[1];
+        assert_eq!(episode.season, Some(1));
+        assert_eq!(EpisodeInfo::decode(&episode.id).unwrap().numbers(), Some((1, 2)));
+
+        assert_eq!(season_list(&client, id).unwrap()[0].number, Some(1));
+    }
+
+    #[test]
+    fn test_anime_movie() {
+        use crate::get_episode_list::{episode_list, EpisodeList};
+
+        let fixtures = Fixtures::new()
+            .route(Method::GET, "https://simkl.com/anime/38922/mythical-detective-loki-ragnarok", "simkl_anime_loki_ragnarok.html")
+            .status(Method::GET, "https://simkl.com/anime/38922/mythical-detective-loki-ragnarok/episodes", 404);
+
+        match episode_list(&fixtures.client(), "%2Fanime%2F38922%2Fmythical-detective-loki-ragnarok").unwrap() {
+            EpisodeList::Movie { episode } => assert_eq!(episode.title, "Full"),
+            series => panic!("Expected a movie, got {:?}", series),
+        }
+    }
+
+    #[test]
+    fn test_get_details() {
+        use crate::get_details::title_details;
+
+        let details = title_details(&Fixtures::simkl().client(), LOKI_ID).unwrap();
+
+        assert_eq!(details.title, "Loki");
+        assert!(details.synopsis.unwrap().starts_with("After stealing the Tesseract during the events of “Avengers: Endgame,”"));
+        assert_eq!(details.genres, ["Action", "Science Fiction", "Fantasy"]);
+        assert_eq!(details.cast.len(), 2);
+        assert_eq!(details.cast[0].character.as_deref(), Some("Loki"));
+        assert_eq!(details.cast[1].image, None);
+        assert_eq!(details.runtime, Some(50));
+        assert_eq!(details.status.as_deref(), Some("Ended"));
+        assert_eq!(details.network.as_deref(), Some("Disney+"));
+        assert_eq!(details.ratings.simkl, Some(8.1));
+        assert_eq!(details.ratings.simkl_votes, Some(25104));
+        assert_eq!(details.ratings.imdb, Some(8.2));
+        assert_eq!(details.backdrop.as_deref(), Some("https://simkl.in/fanart/16/1634585263b1c6da2f_medium.jpg"));
+        assert_eq!(details.trailer.as_deref(), Some("https://www.youtube.com/embed/nW948Va-l10"));
+
+        let details = title_details(&Fixtures::simkl().client(), LOKI_IN_TRAINING_ID).unwrap();
+        assert_eq!(details.title, "LEGO Marvel Avengers: Loki in Training");
+        assert!(details.genres.is_empty());
+        assert_eq!(details.runtime, None);
+    }
+
+    #[test]
+    fn test_get_details_shares_title_page() {
+        use crate::get_details::title_details;
+        use crate::get_episode_list::episode_list;
+
+        let fixtures = Fixtures::simkl();
+        let client = fixtures.client();
+
+        title_details(&client, LOKI_ID).unwrap();
+        episode_list(&client, LOKI_ID).unwrap();
+
+        assert_eq!(fixtures.requested("https://simkl.com/tv/1074318/loki"), 1);
+    }
+
+    #[test]
+    fn test_parse_external_id() {
+        use crate::resolve_id::{ExternalId, IdSource, IdType};
+
+        let parse = |input: &str, id_type: Option<IdType>| ExternalId::parse(input, None, id_type).unwrap();
+
+        assert_eq!(parse("tt9140554", None), ExternalId::Imdb(String::from("tt9140554")));
+        assert_eq!(parse("https://www.imdb.com/title/tt9140554/", None), ExternalId::Imdb(String::from("tt9140554")));
+        assert_eq!(parse("84958", Some(IdType::Tv)), ExternalId::Tmdb { id: String::from("84958"), id_type: IdType::Tv });
+        assert_eq!(parse("https://www.themoviedb.org/movie/1016121-lego", None), ExternalId::Tmdb { id: String::from("1016121"), id_type: IdType::Movie });
+        assert_eq!(parse("1074318", None), ExternalId::Simkl(String::from("1074318")));
+        assert_eq!(parse("https://simkl.com/tv/1074318/loki/episodes", None), ExternalId::SimklPath(String::from("/tv/1074318/loki")));
+        assert_eq!(parse("https://simkl.com/tv/1074318", None), ExternalId::Simkl(String::from("1074318")));
+
+        assert!(matches!(ExternalId::parse("84958", Some(IdSource::Tmdb), None), Err(Error::InvalidArguments(_))));
+        assert!(matches!(ExternalId::parse("https://example.com/84958", None, None), Err(Error::InvalidArguments(_))));
+    }
+
+    #[test]
+    fn test_resolve_id() {
+        use crate::get_episode_list::get_link_id;
+        use crate::resolve_id::{resolve_external_id, ExternalId};
+
+        let fixtures = Fixtures::simkl()
+            .route(Method::GET, "https://simkl.com/redirect?to=Simkl&imdb=tt9140554", "simkl_tv_loki.html");
+        let client = fixtures.client();
+
+        let resolved = resolve_external_id(&client, &ExternalId::Imdb(String::from("tt9140554")), None).unwrap();
+        assert_eq!(resolved.id.as_deref(), Some(LOKI_ID));
+        assert_eq!(resolved.episode_id, None);
+
+        // The redirect already fetched the title page.
+        get_link_id(&client, "/tv/1074318/loki").unwrap();
+        assert_eq!(fixtures.requested("https://simkl.com/tv/1074318/loki"), 0);
+    }
+
+    #[test]
+    fn test_resolve_id_simkl_unreachable() {
+        use crate::get_episode_server::episode_servers;
+        use crate::get_server::EpisodeInfo;
+        use crate::resolve_id::{resolve_external_id, ExternalId, IdType};
+
+        let fixtures = Fixtures::new()
+            .status(Method::GET, "https://simkl.com/redirect?to=Simkl&tmdb=84958&type=tv", 503)
+            .status(Method::GET, "https://simkl.com/redirect?to=Simkl&imdb=tt9140554", 503);
+        let client = fixtures.client();
+        let loki = ExternalId::Tmdb { id: String::from("84958"), id_type: IdType::Tv };
+
+        let resolved = resolve_external_id(&client, &loki, Some((1, 3))).unwrap();
+        assert_eq!(resolved.id, None);
+
+        let servers = episode_servers(&resolved.episode_id.unwrap()).unwrap();
+        let episode_info = EpisodeInfo::decode(&servers["SERVER"][0].id).unwrap();
+        assert_eq!(episode_info.tmdb_id, "84958");
+        assert_eq!(episode_info.numbers(), Some((1, 3)));
+
+        // Without a position a show cannot be turned into an episode, nor an IMDB id into a TMDB one.
+        assert!(resolve_external_id(&client, &loki, None).is_err());
+        assert!(resolve_external_id(&client, &ExternalId::Imdb(String::from("tt9140554")), None).is_err());
+    }
+
+    /// The seasons of a series, or a panic for a movie.
+    fn seasons(episode_list: crate::get_episode_list::EpisodeList) -> Vec<crate::get_episode_list::Season> {
+        match episode_list {
+            crate::get_episode_list::EpisodeList::Series { seasons } => seasons,
+            movie => panic!("Expected a series, got {:?}", movie),
+        }
+    }
+
+    #[test]
+    fn test_get_episode_list() {
+        use serde_json::{from_str, Value};
+        use crate::get_episode_list::episode_list;
+
+        let episode_list = episode_list(&Fixtures::simkl().client(), LOKI_ID).unwrap();
+        assert!(episode_list.found());
+
+        let legacy_data = episode_list.legacy_data();
+        assert_eq!(legacy_data.len(), 4);
+        assert_eq!(legacy_data[1][0][1].title, "Breaking Brad");
+
+        let seasons = seasons(episode_list);
+        assert_eq!(seasons.len(), 4);
+        assert_eq!(seasons[0].number, Some(1));
+        assert_eq!(seasons[0].title, "Season 1");
+        assert_eq!(seasons[0].episode_count, 3);
+        assert_eq!(seasons[0].poster.as_deref(), Some("https://simkl.in/posters/16/1634585263b1c6da2f_s1_ca.jpg"));
+        assert_eq!(seasons[1].episode_count, 2);
+        assert_eq!(seasons[1].poster, None);
+        assert_eq!(seasons[3].title, "Specials");
+
+        let episode = &seasons[1].episodes[1];
+        assert_eq!(episode.index, 1);
+        assert_eq!(episode.title, "Breaking Brad");
+
+        let episode_id: Value = from_str(&decode(&episode.id).unwrap()).unwrap();
+        assert_eq!(episode_id["id_type"], "tv");
+        assert_eq!(episode_id["imdb_id"], "tt9140554");
+        assert_eq!(episode_id["tmdb_id"], "84958");
+        assert_eq!(episode_id["ids"]["tvdb_id"], "362472");
+        assert_eq!(episode_id["season"], 2);
+        assert_eq!(episode_id["episode"], 2);
+    }
+
+    #[test]
+    fn test_get_episode_list_slice() {
+        use crate::get_episode_list::{episode_list_slice, EpisodeFilter, EpisodeRange};
+
+        let client = Fixtures::simkl().client();
+
+        let season = |filter: EpisodeFilter| seasons(episode_list_slice(&client, LOKI_ID, &filter).unwrap());
+
+        let only_season_1 = season(EpisodeFilter { season: Some(1), episode_range: None });
+        assert_eq!(only_season_1.len(), 1);
+        assert_eq!(only_season_1[0].episodes.len(), 3);
+
+        let range = season(EpisodeFilter {
+            season: Some(1),
+            episode_range: Some(EpisodeRange { from: Some(2), to: None }),
+        });
+        assert_eq!(range[0].episode_count, 3);
+        assert_eq!(range[0].episodes.iter().map(|episode| episode.title.as_str()).collect::<Vec<_>>(), ["The Variant", "Lamentis"]);
+
+        // Season 3 has no episode 2, so it is left out.
+        let range = season(EpisodeFilter {
+            season: None,
+            episode_range: Some(EpisodeRange { from: Some(2), to: Some(2) }),
+        });
+        assert_eq!(range.iter().map(|season| season.number).collect::<Vec<_>>(), [Some(1), Some(2), Some(0)]);
+
+        assert!(season(EpisodeFilter { season: Some(12), episode_range: None }).is_empty());
+    }
+
+    #[test]
+    fn test_get_season_list() {
+        use crate::get_episode_list::season_list;
+
+        let fixtures = Fixtures::simkl();
+        let seasons = season_list(&fixtures.client(), LOKI_ID).unwrap();
+
+        assert_eq!(seasons.iter().map(|season| (season.number, season.episode_count)).collect::<Vec<_>>(), [
+            (Some(1), 3), (Some(2), 2), (Some(3), 1), (Some(0), 1),
+        ]);
+        assert_eq!(seasons[3].title, "Specials");
+        // Headings only need the episodes page.
+        assert_eq!(fixtures.requested("https://simkl.com/tv/1074318/loki"), 0);
+
+        assert!(season_list(&fixtures.client(), LOKI_IN_TRAINING_ID).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_get_episode_list_specials() {
+        use crate::get_episode_list::episode_list;
+        use crate::get_episode_server::episode_servers;
+        use crate::get_server::EpisodeInfo;
+        use crate::provider::moviesapi::get_keys;
+
+        let fixtures = Fixtures::simkl()
+            .route(Method::GET, "https://ww2.moviesapi.to/tv/84958/0/2", "moviesapi_tv.html");
+        let client = fixtures.client();
+
+        let seasons = seasons(episode_list(&client, LOKI_ID).unwrap());
+        let special = &seasons[3].episodes[0];
+        assert_eq!(special.index, 0);
+        assert_eq!((special.season, special.number), (Some(0), Some(2)));
+
+        let servers = episode_servers(&special.id).unwrap();
+        let episode_info = EpisodeInfo::decode(&servers["SERVER"][0].id).unwrap();
+        assert_eq!(episode_info.numbers(), Some((0, 2)));
+
+        get_keys(&client, &episode_info).unwrap();
+        assert_eq!(fixtures.requested("https://ww2.moviesapi.to/tv/84958/0/2"), 1);
+    }
+
+    #[test]
+    fn test_get_adjacent_episodes() {
+        use crate::cache::Endpoint;
+        use crate::get_adjacent_episodes::adjacent_episodes;
+        use crate::get_episode_list::episode_list;
+
+        let fixtures = Fixtures::simkl();
+        let client = fixtures.client();
+        let seasons = seasons(client.cache().get_or_fetch(client.settings(), Endpoint::EpisodeList, "/tv/1074318/loki", false, || {
+            episode_list(&client, LOKI_ID)
+        }).unwrap());
+
+        // The last episode of season 1 rolls over into season 2.
+        let adjacent = adjacent_episodes(&client, &seasons[0].episodes[2].id).unwrap();
+        assert_eq!(adjacent.previous.unwrap().title, "The Variant");
+        assert_eq!(adjacent.next.unwrap().title, "Ouroboros");
+
+        let adjacent = adjacent_episodes(&client, &seasons[0].episodes[0].id).unwrap();
+        assert!(adjacent.previous.is_none());
+
+        // Regular seasons skip the specials, and the specials stay among themselves.
+        let adjacent = adjacent_episodes(&client, &seasons[2].episodes[0].id).unwrap();
+        assert_eq!(adjacent.previous.unwrap().title, "Breaking Brad");
+        assert!(adjacent.next.is_none());
+
+        let adjacent = adjacent_episodes(&client, &seasons[3].episodes[0].id).unwrap();
+        assert!(adjacent.previous.is_none() && adjacent.next.is_none());
+
+        // Every lookup reuses the cached list.
+        assert_eq!(fixtures.requested("https://simkl.com/tv/1074318/loki/episodes"), 1);
+    }
+
+    #[test]
+    fn test_legacy_episode_id() {
+        use crate::get_server::EpisodeInfo;
+
+        // Handed out with 0-based positions before the real numbers were parsed.
+        let episode_info = EpisodeInfo::decode("%7B%22e%22%3A0%2C%22id_type%22%3A%22tv%22%2C%22imdb_id%22%3A%22tt9140554%22%2C%22s%22%3A1%2C%22source_id%22%3A%22sflix2%22%2C%22tmdb_id%22%3A%2284958%22%7D").unwrap();
+
+        assert_eq!(episode_info.numbers(), Some((2, 1)));
+        assert_eq!(episode_info.provider, "moviesapi");
+    }
+
+    #[test]
+    fn test_get_episode_list_metadata() {
+        use crate::get_episode_list::episode_list;
+
+        let seasons = seasons(episode_list(&Fixtures::simkl().client(), LOKI_ID).unwrap());
+
+        let episode = &seasons[0].episodes[0];
+        assert_eq!(episode.season, Some(1));
+        assert_eq!(episode.number, Some(1));
+        assert_eq!(episode.air_date.as_deref(), Some("2021-06-09"));
+        assert_eq!(episode.image.as_deref(), Some("https://simkl.in/episodes/84/8412a7f3c1_w.jpg"));
+        assert_eq!(episode.overview.as_deref(), Some("After stealing the Tesseract in “Avengers: Endgame,” Loki lands before the Time Variance Authority."));
+        assert!(episode.aired);
+
+        let episode = &seasons[0].episodes[2];
+        assert_eq!(episode.image, None);
+        assert_eq!(episode.overview, None);
+
+        let episode = &seasons[2].episodes[0];
+        assert_eq!(episode.season, Some(3));
+        assert!(!episode.aired);
+    }
+
+    #[test]
+    fn test_get_episode_list_movie() {
+        use crate::get_episode_list::{episode_list, EpisodeList};
+
+        let episode_list = episode_list(&Fixtures::simkl().client(), LOKI_IN_TRAINING_ID).unwrap();
+
+        assert!(matches!(&episode_list, EpisodeList::Movie { episode } if episode.title == "Full"));
+        assert!(!episode_list.found());
+        assert_eq!(episode_list.legacy_data().len(), 1);
+    }
+
+    #[test]
+    fn test_get_keys() {
+        use crate::provider::moviesapi::get_keys;
+
+        let keys = get_keys(&Fixtures::simkl().client(), &loki_episode_info()).unwrap();
+
+        assert_eq!(keys.scrapify_url, SCRAPIFY_URL);
+        assert_eq!(keys.encryption_key, "b7f1c9d2e4a64f0d8e3c5a1b9d7e2f60");
+        assert_eq!(keys.player_api_key, "c2f5e8a1d4b7");
+    }
+
+    #[test]
+    fn test_get_hls_and_track() {
+        use crate::provider::moviesapi::{get_hls_and_track, get_keys};
+
+        let fixtures = Fixtures::simkl();
+        let client = fixtures.client();
+        let keys = get_keys(&client, &loki_episode_info()).unwrap();
+        let hls_and_track = get_hls_and_track(&client, &loki_episode_info(), &keys).unwrap();
+
+        assert_eq!(hls_and_track.url, "https://ax.1hd.su/vz-4b1f2c.b-cdn.net/hls/84958/1/1/master.m3u8");
+        assert_eq!(hls_and_track.tracks.len(), 2);
+        assert_eq!(hls_and_track.tracks[0].label.as_deref(), Some("English"));
+        assert_eq!(hls_and_track.tracks[0].kind, "subtitles");
+
+        let requests = fixtures.requests.lock().unwrap();
+        assert!(requests.iter().all(|req| req.headers["user-agent"] == crate::DEFAULT_USER_AGENT));
+
+        let scrapify = requests.iter()
+            .find(|req| req.url == SCRAPIFY_URL)
+            .unwrap();
+        assert_eq!(scrapify.headers["x-player-key"], "c2f5e8a1d4b7");
+        assert!(scrapify.body.as_ref().unwrap()["payload"].as_str().unwrap().starts_with("U2FsdGVkX1"));
+    }
+
+    #[test]
+    fn test_get_episode_server_sources() {
+        use crate::get_episode_server::episode_servers;
+        use crate::get_server::EpisodeInfo;
+        use crate::provider::moviesapi::{source, Source};
+
+        let servers = episode_servers("%7B%22e%22%3A0%2C%22id_type%22%3A%22tv%22%2C%22imdb_id%22%3A%22tt9140554%22%2C%22s%22%3A0%2C%22tmdb_id%22%3A%2284958%22%7D").unwrap();
+        let sources = servers["SERVER"].iter()
+            .map(|server| source(&EpisodeInfo::decode(&server.id).unwrap()).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(sources, Source::ALL);
+        assert_eq!(EpisodeInfo::decode(&servers["SERVER"][0].id).unwrap().provider, "moviesapi");
+        assert_eq!(servers["SERVER"][1].title, "M4UHD");
+
+        // Scrapify does not say how many mirrors a source has, so each is listed on its first one, whatever
+        // mirror the episode id passed in named.
+        assert!(servers["SERVER"].iter().all(|server| server.index == 0 && server.mirrors.is_none()
+            && EpisodeInfo::decode(&server.id).unwrap().srv == 0));
+
+        let episode_id = EpisodeInfo { srv: 2, ..loki_episode_info() }.encode().unwrap();
+        let servers = episode_servers(&episode_id).unwrap();
+        assert!(servers["SERVER"].iter().all(|server| EpisodeInfo::decode(&server.id).unwrap().srv == server.index));
+    }
+
+    #[test]
+    fn test_probe_servers() {
+        use crate::get_episode_server::{episode_servers, probe_servers};
+        use crate::get_server::EpisodeInfo;
+
+        // Any mirror not routed here has no stream.
+        let fixtures = Fixtures::simkl()
+            .reroute(Method::POST, SCRAPIFY_URL, "scrapify_fetch_not_found.json")
+            .scrapify("sflix2", 0, "scrapify_fetch.json")
+            .scrapify("sflix2", 1, "scrapify_fetch_m4uhd.json")
+            .scrapify("m4uhd", 0, "scrapify_fetch_m4uhd.json")
+            .timeout(Method::POST, &scrapify_route("m4uhd", 1))
+            .scrapify("insertunit", 0, "scrapify_fetch_insertunit.json")
+            .route(Method::GET, "https://ax.1hd.su/vz-4b1f2c.b-cdn.net/hls/84958/1/1/master.m3u8", "moviesapi_master.m3u8")
+            // Insertunit resolves to a playlist that is already gone.
+            .status(Method::GET, "https://ax.1hd.su/cdn.insertunit.com/hls/84958/s1e1/index.m3u8", 404);
+
+        let mut servers = episode_servers("%7B%22e%22%3A0%2C%22id_type%22%3A%22tv%22%2C%22imdb_id%22%3A%22tt9140554%22%2C%22s%22%3A0%2C%22tmdb_id%22%3A%2284958%22%7D").unwrap()
+            .remove("SERVER")
+            .unwrap();
+        probe_servers(&fixtures.client(), &mut servers);
+
+        // The probes start on a cold keys cache, yet fetch the keys only once between them.
+        assert_eq!(fixtures.requested("https://ww2.moviesapi.to/tv/84958/1/1"), 1);
+        assert_eq!(fixtures.requested("https://ww2.moviesapi.to/assets/index-4f2a9c1e.js"), 1);
+
+        // Each mirror found is listed on its own, up to the first without a stream: sflix2 and m4uhd have 2,
+        // insertunit 1 and bmovies none, which still lists it as unavailable.
+        assert_eq!(fixtures.requested(SCRAPIFY_URL), 3 + 3 + 1 + 2);
+        let mut listed = servers.iter()
+            .map(|server| (server.title.as_str(), server.index, server.mirrors))
+            .collect::<Vec<_>>();
+        listed.sort();
+        assert_eq!(listed, [
+            ("BMovies", 0, Some(0)),
+            ("Insertunit", 0, Some(1)),
+            ("M4UHD", 0, Some(2)),
+            ("M4UHD (Mirror 2)", 1, Some(2)),
+            ("SFlix2", 0, Some(2)),
+            ("SFlix2 (Mirror 2)", 1, Some(2)),
+        ]);
+        assert!(servers.iter().all(|server| EpisodeInfo::decode(&server.id).unwrap().srv == server.index));
+
+        // Best first.
+        let probes = servers.iter().map(|server| server.probe.clone().unwrap()).collect::<Vec<_>>();
+        assert_eq!(probes.iter().map(|probe| (probe.available, probe.max_quality)).collect::<Vec<_>>(),
+            [(true, Some(1080)), (true, Some(720)), (true, Some(720)), (false, None), (false, None), (false, None)]);
+        assert_eq!((servers[0].title.as_str(), servers[0].index), ("SFlix2", 0));
+        let mut errors = probes[3..].iter().map(|probe| probe.error.as_ref().unwrap().code.as_str()).collect::<Vec<_>>();
+        errors.sort();
+        assert_eq!(errors, ["PARSE_FAILED", "TIMEOUT", "UPSTREAM_HTTP"]);
+    }
+
+    #[test]
+    fn test_probe_servers_within_deadline() {
+        use std::time::Duration;
+        use crate::error::ErrorCode;
+        use crate::get_episode_server::{episode_servers, probe_servers};
+
+        let mut servers = episode_servers("%7B%22e%22%3A0%2C%22id_type%22%3A%22tv%22%2C%22imdb_id%22%3A%22tt9140554%22%2C%22s%22%3A0%2C%22tmdb_id%22%3A%2284958%22%7D").unwrap()
+            .remove("SERVER")
+            .unwrap();
+
+        // The call's deadline is already spent, which `probe_timeout_ms` must not extend.
+        let client = Fixtures::simkl().client().with_deadline(Duration::ZERO);
+        probe_servers(&client, &mut servers);
+
+        assert!(servers.iter().all(|server| server.probe.as_ref().unwrap().error.as_ref().unwrap().code == ErrorCode::Timeout));
+    }
+
+    #[test]
+    fn test_parse_stream() {
+        use crate::provider::moviesapi::{parse_stream, Source};
+
+        let m4uhd = parse_stream(Source::M4uhd, &read_fixture("scrapify_fetch_m4uhd.json")).unwrap();
+        assert_eq!(m4uhd.url, "https://m4uhd.cdn-7c1e.net/84958/1/1/720p.mp4");
+        assert_eq!(m4uhd._type, "mp4");
+        assert_eq!(m4uhd.tracks[0].label.as_deref(), Some("English"));
+        assert_eq!(m4uhd.tracks[0].kind, "subtitles");
+
+        let insertunit = parse_stream(Source::Insertunit, &read_fixture("scrapify_fetch_insertunit.json")).unwrap();
+        assert_eq!(insertunit._type, "hls");
+        assert!(insertunit.tracks.is_empty());
+
+        let error = parse_stream(Source::Bmovies, &read_fixture("scrapify_fetch_not_found.json")).unwrap_err();
+        assert!(error.to_string().contains("BMovies returned no stream: Title not found"));
+
+        // Both spellings of a field present: the preferred one wins instead of failing on a duplicate.
+        let both_keys = parse_stream(Source::Sflix2, &read_fixture("scrapify_fetch_both_keys.json")).unwrap();
+        assert_eq!(both_keys.url, "https://vz-4b1f2c.b-cdn.net/hls/84958/1/1/master.m3u8?token=3f9a1c");
+        assert_eq!(both_keys._type, "hls");
+        assert_eq!(both_keys.tracks.len(), 1);
+        assert_eq!(both_keys.tracks[0].file, "https://cc.1hd.su/84958/1/1/eng.vtt");
+        assert_eq!(both_keys.tracks[0].label.as_deref(), Some("English"));
+
+        let error = parse_stream(Source::M4uhd, &read_fixture("scrapify_fetch_not_found_both_keys.json")).unwrap_err();
+        assert!(error.to_string().contains("M4UHD returned no stream: Title not found"));
+    }
+
+    #[test]
+    fn test_resolve_server_unknown_provider() {
+        use crate::get_server::{resolve_server, EpisodeInfo};
+
+        let episode_info = EpisodeInfo { source_id: String::from("vidsrc"), ..loki_episode_info() };
+
+        assert!(matches!(resolve_server(&Fixtures::simkl().client(), &episode_info), Err(Error::InvalidArguments(_))));
+
+        let episode_info = EpisodeInfo { provider: String::from("vidsrc"), ..loki_episode_info() };
+        assert!(matches!(resolve_server(&Fixtures::simkl().client(), &episode_info), Err(Error::InvalidArguments(_))));
+    }
+
+    #[test]
+    fn test_resolve_through_providers() {
+        use crate::get_episode_list::get_link_id;
+        use crate::provider::{provider, require_playable, PROVIDERS};
+
+        let fixtures = Fixtures::simkl()
+            .route(Method::GET, "https://simkl.com/anime/2209483/frieren", "simkl_anime_frieren.html");
+        let episode_info = loki_episode_info();
+
+        let registered = provider(&episode_info.provider).unwrap();
+        assert!(PROVIDERS.iter().any(|provider| provider.id() == registered.id()));
+        assert!(registered.servers(&episode_info).iter().any(|server| server.source_id == episode_info.source_id));
+
+        let result = registered.resolve(&fixtures.client(), &episode_info).unwrap();
+        assert_eq!(result.data.sources[0].file, "https://ax.1hd.su/vz-4b1f2c.b-cdn.net/hls/84958/1/1/master.m3u8");
+
+        // Every registered provider needs a TMDB id, which simkl does not link for frieren.
+        assert!(require_playable(&get_link_id(&fixtures.client(), "/tv/1074318/loki").unwrap()).is_ok());
+        match require_playable(&get_link_id(&fixtures.client(), "/anime/2209483/frieren").unwrap()) {
+            Err(Error::LinkIdNotFound(message)) => assert_eq!(message, "TMDB ID not found."),
+            result => panic!("Expected LinkIdNotFound, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_resolve_server() {
+        use crate::get_server::resolve_server;
+
+        let fixtures = Fixtures::simkl();
+        let result = resolve_server(&fixtures.client(), &loki_episode_info()).unwrap();
+
+        assert_eq!(result.data.sources[0].file, "https://ax.1hd.su/vz-4b1f2c.b-cdn.net/hls/84958/1/1/master.m3u8");
+        assert_eq!(result.config.host, "ax.1hd.su");
+        assert_eq!(fixtures.requested("https://ww2.moviesapi.to/tv/84958/1/1"), 1);
+    }
+
+    #[test]
+    fn test_resolve_server_sources() {
+        use crate::get_server::{resolve_server, EpisodeInfo};
+
+        let resolve = |source_id: &str, fixture: &str| {
+            let fixtures = Fixtures::simkl().reroute(Method::POST, SCRAPIFY_URL, fixture);
+
+            resolve_server(&fixtures.client(), &EpisodeInfo { source_id: source_id.to_string(), ..loki_episode_info() }).unwrap()
+        };
+
+        let m4uhd = resolve("m4uhd", "scrapify_fetch_m4uhd.json");
+        assert_eq!(m4uhd.data.sources[0].file, "https://ax.1hd.su/m4uhd.cdn-7c1e.net/84958/1/1/720p.mp4");
+        assert_eq!(m4uhd.data.sources[0]._type, "mp4");
+        assert_eq!(m4uhd.data.tracks[0].file, "https://m4uhd.cdn-7c1e.net/84958/1/1/eng.vtt");
+        assert_eq!(m4uhd.data.tracks[0].label.as_deref(), Some("English"));
+
+        let bmovies = resolve("bmovies", "scrapify_fetch_bmovies.json");
+        assert_eq!(bmovies.data.sources[0].file, "https://ax.1hd.su/bm.vidcdn-3d.net/hls/84958/1/1/playlist.m3u8");
+        assert_eq!(bmovies.data.sources[0]._type, "hls");
+        assert_eq!(bmovies.data.tracks[0].default, Some(true));
+        assert_eq!(bmovies.data.tracks[0].kind, "subtitles");
+
+        let insertunit = resolve("insertunit", "scrapify_fetch_insertunit.json");
+        assert_eq!(insertunit.data.sources[0].file, "https://ax.1hd.su/cdn.insertunit.com/hls/84958/s1e1/index.m3u8");
+        assert_eq!(insertunit.data.sources[0]._type, "hls");
+        assert!(insertunit.data.tracks.is_empty());
+        assert_eq!(insertunit.config.host, "ax.1hd.su");
+    }
+
+    #[test]
+    fn test_resolve_server_mirror() {
+        use crate::get_server::{resolve_server, EpisodeInfo};
+
+        // An id handed out for mirror 2, e.g. by `fallback`, keeps it unless `get_server` is given another.
+        let id = EpisodeInfo { srv: 2, ..loki_episode_info() }.encode().unwrap();
+        let episode_info = EpisodeInfo::decode(&id).unwrap();
+
+        assert_eq!(episode_info.clone().with_mirror(None, None).srv, 2);
+        assert_eq!(episode_info.clone().with_mirror(None, Some(1)).srv, 1);
+        assert_eq!(episode_info.clone().with_mirror(Some(0), Some(1)).srv, 0);
+
+        let fixtures = Fixtures::simkl();
+        assert!(resolve_server(&fixtures.client(), &episode_info.with_mirror(None, None)).is_ok());
+        assert_eq!(fixtures.requested(SCRAPIFY_URL), 1);
+    }
+
+    #[test]
+    fn test_resolve_with_fallback() {
+        use crate::get_server::{resolve_with_fallback, EpisodeInfo};
+
+        let fixtures = Fixtures::simkl()
+            .reroute(Method::POST, SCRAPIFY_URL, "scrapify_fetch_not_found.json")
+            .route(Method::POST, SCRAPIFY_URL, "scrapify_fetch.json");
+
+        // The first mirror of the chosen source plays.
+        let episode_info = EpisodeInfo { srv: 2, ..loki_episode_info() };
+        let (result, fallback) = resolve_with_fallback(&fixtures.client(), &episode_info).unwrap();
+        assert_eq!(result.config.host, "ax.1hd.su");
+        assert_eq!((fallback.source_id.as_str(), fallback.srv), ("sflix2", 0));
+        assert_eq!(EpisodeInfo::decode(&fallback.id).unwrap().srv, 0);
+        assert_eq!(fallback.errors.len(), 1);
+        assert_eq!(fallback.errors[0].srv, 2);
+        assert_eq!(fallback.errors[0].error.code, crate::error::ErrorCode::ParseFailed);
+
+        // Every source is tried before giving up, and each failure is listed.
+        let fixtures = Fixtures::simkl().reroute(Method::POST, SCRAPIFY_URL, "scrapify_fetch_not_found.json");
+
+        let episode_info = EpisodeInfo { source_id: String::from("bmovies"), srv: 1, ..loki_episode_info() };
+        let error = resolve_with_fallback(&fixtures.client(), &episode_info).unwrap_err();
+        let Error::NoServerPlayed(failed) = &error else { panic!("Expected NoServerPlayed, got {:?}", error) };
+        let tried = failed.iter().map(|server| (server.source_id.as_str(), server.srv)).collect::<Vec<_>>();
+        assert_eq!(tried, [("bmovies", 1), ("bmovies", 0), ("sflix2", 0), ("m4uhd", 0), ("insertunit", 0)]);
+        assert_eq!(error.to_string(), "No server played: moviesapi/bmovies srv 1 (PARSE_FAILED), moviesapi/bmovies srv 0 (PARSE_FAILED), \
+            moviesapi/sflix2 srv 0 (PARSE_FAILED), moviesapi/m4uhd srv 0 (PARSE_FAILED), moviesapi/insertunit srv 0 (PARSE_FAILED)");
+        assert_eq!(error.info().code, crate::error::ErrorCode::NoServerPlayed);
+        assert_eq!(fixtures.requested(SCRAPIFY_URL), 5);
+
+        // A source that times out or errors is skipped, and the mirrors above the chosen one are tried first.
+        let fixtures = Fixtures::simkl()
+            .clear(Method::POST, SCRAPIFY_URL)
+            .timeout(Method::POST, SCRAPIFY_URL)
+            .status(Method::POST, SCRAPIFY_URL, 500)
+            .route(Method::POST, SCRAPIFY_URL, "scrapify_fetch_not_found.json")
+            .route(Method::POST, SCRAPIFY_URL, "scrapify_fetch.json");
+
+        let (_, fallback) = resolve_with_fallback(&fixtures.client(), &loki_episode_info()).unwrap();
+        assert_eq!((fallback.source_id.as_str(), fallback.srv), ("m4uhd", 0));
+        let failed = fallback.errors.iter()
+            .map(|server| (server.source_id.as_str(), server.srv, server.error.code.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(failed, [("sflix2", 0, "TIMEOUT"), ("sflix2", 1, "UPSTREAM_HTTP"), ("sflix2", 2, "PARSE_FAILED")]);
+        assert_eq!(fallback.errors[1].error.http_status, Some(500));
+
+        // What every source shares failing is not down to the server: the player page is fetched once, and
+        // its error returned as is.
+        let player_url = "https://ww2.moviesapi.to/tv/84958/1/1";
+        let fixtures = Fixtures::simkl().reroute_status(Method::GET, player_url, 404);
+        let error = resolve_with_fallback(&fixtures.client(), &loki_episode_info()).unwrap_err();
+        assert!(matches!(error, Error::UpstreamHttp { status: 404, .. }), "{:?}", error);
+        assert_eq!(fixtures.requested(player_url), 1);
+        assert_eq!(fixtures.requested(SCRAPIFY_URL), 0);
+    }
+
+    #[test]
+    fn test_resolve_with_fallback_stops() {
+        use std::time::Duration;
+        use crate::get_server::{resolve_with_fallback, EpisodeInfo};
+        use crate::runtime::CancelToken;
+
+        /// Cancels the call as soon as scrapify has answered.
+        struct CancelOnScrapify {
+            fixtures: Fixtures,
+            token: CancelToken,
+        }
+
+        impl Transport for CancelOnScrapify {
+            fn send(&self, request: Request) -> Result<Response> {
+                let res = self.fixtures.send(request.clone());
+                if request.url == SCRAPIFY_URL {
+                    self.token.cancel();
+                }
+                res
+            }
+        }
+
+        let fixtures = Fixtures::simkl().reroute(Method::POST, SCRAPIFY_URL, "scrapify_fetch_not_found.json");
+
+        // Cancelling stops at the server being tried.
+        let token = CancelToken::new();
+        let client = Client::with_transport(CancelOnScrapify { fixtures: fixtures.clone(), token: token.clone() })
+            .with_settings(fixtures.client().settings().clone())
+            .with_cancel_token(token);
+        assert!(matches!(resolve_with_fallback(&client, &loki_episode_info()), Err(Error::Cancelled)));
+        assert_eq!(fixtures.requested(SCRAPIFY_URL), 1);
+
+        // So does anything that would fail every other server the same way.
+        let episode_info = EpisodeInfo { tmdb_id: String::new(), ..loki_episode_info() };
+        assert!(matches!(resolve_with_fallback(&fixtures.client(), &episode_info), Err(Error::LinkIdNotFound(_))));
+
+        let client = fixtures.client().with_deadline(Duration::ZERO);
+        assert!(matches!(resolve_with_fallback(&client, &loki_episode_info()), Err(Error::Timeout(_))));
+        assert_eq!(fixtures.requested(SCRAPIFY_URL), 1);
+    }
+
+    #[test]
+    fn test_settings_stand_in_server() {
+        use crate::search::{search_titles, MediaType};
+        use crate::get_server::resolve_server;
+
+        let settings = Settings {
+            source_base_url: String::from("http://127.0.0.1:8080"),
+            poster_base_url: String::from("http://127.0.0.1:8080/posters"),
+            server_base_url: String::from("http://127.0.0.1:8081"),
+            scrapify_url: String::from("http://127.0.0.1:8081/scrapify"),
+            proxy_host: String::new(),
+            ..Settings::default()
+        };
+        assert_eq!(settings.source_host(), "127.0.0.1:8080");
+
+        let fixtures = Fixtures::new()
+            .route(Method::POST, "http://127.0.0.1:8080/ajax/full/search.php?s=loki&type=movies&sort=0", "simkl_search_movies.json")
+            .route(Method::POST, "http://127.0.0.1:8080/ajax/full/search.php?s=loki&type=tv&sort=0", "simkl_search_tv.json")
+            .route(Method::POST, "http://127.0.0.1:8080/ajax/full/search.php?s=loki&type=anime&sort=0", "simkl_search_anime.json")
+            .route(Method::GET, "http://127.0.0.1:8081/tv/84958/1/1", "moviesapi_tv.html")
+            .route(Method::GET, "http://127.0.0.1:8081/assets/index-4f2a9c1e.js", "moviesapi_module.js")
+            .route(Method::POST, "http://127.0.0.1:8081/scrapify", "scrapify_fetch.json");
+        let client = fixtures.client().with_settings(settings);
+
+        let data = search_titles(&client, "loki", MediaType::All).unwrap();
+        assert_eq!(data[1].cover, "http://127.0.0.1:8080/posters/16/1634585263b1c6da2f_m.webp");
+
+        let result = resolve_server(&client, &loki_episode_info()).unwrap();
+        assert_eq!(result.data.sources[0].file, "https://vz-4b1f2c.b-cdn.net/hls/84958/1/1/master.m3u8?token=3f9a1c");
+
+        let requests = fixtures.requests.lock().unwrap();
+        assert_eq!(requests[0].headers["host"], "127.0.0.1:8080");
+    }
+
+    #[test]
+    fn test_retry_transient_status() {
+        use crate::get_episode_list::get_link_id;
+
+        let url = "https://simkl.com/tv/1074318/loki";
+        let fixtures = Fixtures::new()
+            .status(Method::GET, url, 503)
+            .status(Method::GET, url, 429)
+            .route(Method::GET, url, "simkl_tv_loki.html");
+
+        let link_id = get_link_id(&fixtures.client(), "/tv/1074318/loki").unwrap();
+
+        assert_eq!(link_id.tmdb_id.as_deref(), Some("84958"));
+        assert_eq!(fixtures.requested(url), 3);
+    }
+
+    #[test]
+    fn test_retries_exhausted() {
+        use crate::get_episode_list::get_link_id;
+
+        let url = "https://simkl.com/tv/1074318/loki";
+        let fixtures = Fixtures::new().status(Method::GET, url, 502);
+
+        let error = get_link_id(&fixtures.client(), "/tv/1074318/loki").unwrap_err();
+
+        let info = error.info();
+        assert_eq!(info.code, crate::error::ErrorCode::RetriesExhausted);
+        assert_eq!(info.http_status, Some(502));
+        assert_eq!(info.attempts, Some(3));
+        assert_eq!(fixtures.requested(url), 3);
+    }
+
+    #[test]
+    fn test_no_retry() {
+        use crate::get_episode_list::get_link_id;
+
+        // Not found is not transient.
+        let url = "https://simkl.com/tv/1074318/loki";
+        let fixtures = Fixtures::new().status(Method::GET, url, 404);
+        assert!(matches!(get_link_id(&fixtures.client(), "/tv/1074318/loki"), Err(Error::UpstreamHttp { status: 404, .. })));
+        assert_eq!(fixtures.requested(url), 1);
+
+        // A POST is only replayed when the upstream never handled it.
+        let url = SCRAPIFY_URL;
+        let fixtures = Fixtures::new().status(Method::POST, url, 500);
+        assert!(fixtures.client().post(url, Default::default(), None).is_err());
+        assert_eq!(fixtures.requested(url), 1);
+    }
+
+    #[test]
+    fn test_deadline() {
+        use std::time::Duration;
+        use crate::get_episode_list::get_link_id;
+
+        let client = Fixtures::simkl().client().with_deadline(Duration::ZERO);
+
+        assert!(matches!(get_link_id(&client, "/tv/1074318/loki"), Err(Error::Timeout(_))));
+    }
+
+    /// Never answers within the lifetime of a test.
+    struct Stall;
+
+    impl Transport for Stall {
+        fn send(&self, _request: Request) -> Result<Response> {
+            std::thread::sleep(std::time::Duration::from_secs(30));
+            Err(Error::Network(String::from("Stalled")))
+        }
+    }
+
+    #[test]
+    fn test_cancel_in_flight() {
+        use std::thread;
+        use std::time::{Duration, Instant};
+        use crate::get_episode_list::get_link_id;
+        use crate::runtime::CancelToken;
+
+        let token = CancelToken::new();
+        let client = Client::with_transport(Stall).with_cancel_token(token.clone());
+
+        let canceller = token.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            canceller.cancel();
+        });
+
+        let started = Instant::now();
+        assert!(matches!(get_link_id(&client, "/tv/1074318/loki"), Err(Error::Cancelled)));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_deadline_in_flight() {
+        use std::time::{Duration, Instant};
+        use crate::get_episode_list::get_link_id;
+        use crate::runtime::CancelToken;
+
+        // A cancellable call gives up at its deadline even when the request, queued or stalled, never returns by then.
+        let client = Client::with_transport(Stall)
+            .with_cancel_token(CancelToken::new())
+            .with_deadline(Duration::from_millis(100));
+
+        let started = Instant::now();
+        assert!(matches!(get_link_id(&client, "/tv/1074318/loki"), Err(Error::Timeout(_))));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_cancel_queued() {
+        use std::sync::mpsc;
+        use std::time::Duration;
+        use crate::runtime;
+
+        // Registered but not picked up by a worker yet, as when every worker is busy.
+        let (sender, receiver) = mpsc::channel::<&str>();
+        let (handle, token) = runtime::register(Box::new(move || sender.send("CANCELLED").unwrap()));
+
+        assert!(runtime::cancel(handle));
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(5)).unwrap(), "CANCELLED");
+        assert!(token.is_cancelled());
+
+        // The worker then skips it, and the handle is gone.
+        assert!(!runtime::start(handle));
+        assert!(!runtime::cancel(handle));
+    }
+
+    #[test]
+    fn test_cancel_reuses_request_threads() {
+        use std::thread;
+        use std::time::{Duration, Instant};
+        use crate::runtime::{self, CancelToken, MAX_REQUEST_THREADS};
+
+        struct Slow;
+
+        impl Transport for Slow {
+            fn send(&self, _request: Request) -> Result<Response> {
+                thread::sleep(Duration::from_millis(200));
+                Err(Error::Network(String::from("Slow")))
+            }
+        }
+
+        // Twice as many abandoned requests as there are request threads.
+        let started = Instant::now();
+        let calls = (0..MAX_REQUEST_THREADS * 2)
+            .map(|_| thread::spawn(|| {
+                let token = CancelToken::new();
+                let client = Client::with_transport(Slow).with_cancel_token(token.clone());
+                let canceller = thread::spawn(move || {
+                    thread::sleep(Duration::from_millis(20));
+                    token.cancel();
+                });
+
+                let result = client.get("https://simkl.com/tv/1074318/loki", Default::default());
+                canceller.join().unwrap();
+                result
+            }))
+            .collect::<Vec<_>>();
+
+        for call in calls {
+            assert!(matches!(call.join().unwrap(), Err(Error::Cancelled)));
+        }
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(runtime::request_threads() <= MAX_REQUEST_THREADS);
+    }
+
+    #[test]
+    fn test_cancel_before_request() {
+        use crate::get_server::resolve_server;
+        use crate::runtime::CancelToken;
+
+        let fixtures = Fixtures::simkl();
+        let token = CancelToken::new();
+        token.cancel();
+
+        let client = fixtures.client().with_cancel_token(token);
+
+        assert!(matches!(resolve_server(&client, &loki_episode_info()), Err(Error::Cancelled)));
+        assert!(fixtures.requests.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_keys_cache() {
+        use crate::get_server::resolve_server;
+
+        let fixtures = Fixtures::simkl();
+        let client = fixtures.client();
+
+        resolve_server(&client, &loki_episode_info()).unwrap();
+        resolve_server(&client, &loki_episode_info()).unwrap();
+
+        assert_eq!(fixtures.requested("https://ww2.moviesapi.to/tv/84958/1/1"), 1);
+        assert_eq!(fixtures.requested(SCRAPIFY_URL), 2);
+
+        // Disabled by a zero TTL.
+        let fixtures = Fixtures::simkl();
+        let client = fixtures.client().with_settings(Settings { keys_ttl_ms: 0, ..Settings::default() });
+
+        resolve_server(&client, &loki_episode_info()).unwrap();
+        resolve_server(&client, &loki_episode_info()).unwrap();
+
+        assert_eq!(fixtures.requested("https://ww2.moviesapi.to/tv/84958/1/1"), 2);
+    }
+
+    #[test]
+    fn test_keys_cache_refetch_on_rejection() {
+        use crate::get_server::resolve_server;
+
+        let fixtures = Fixtures::simkl()
+            .reroute(Method::POST, SCRAPIFY_URL, "scrapify_fetch.json")
+            .status(Method::POST, SCRAPIFY_URL, 403)
+            .route(Method::POST, SCRAPIFY_URL, "scrapify_fetch.json");
+        let client = fixtures.client();
+
+        resolve_server(&client, &loki_episode_info()).unwrap();
+        resolve_server(&client, &loki_episode_info()).unwrap();
+
+        assert_eq!(fixtures.requested("https://ww2.moviesapi.to/tv/84958/1/1"), 2);
+        assert_eq!(fixtures.requested(SCRAPIFY_URL), 3);
+    }
+
+    #[test]
+    fn test_keys_cache_on_disk() {
+        use crate::get_server::resolve_server;
+
+        let path = std::env::temp_dir().join(format!("chlaty-moviesapi-keys-{}.json", std::process::id()));
+        let settings = Settings {
+            keys_cache_path: Some(path.to_string_lossy().into_owned()),
+            ..Settings::default()
+        };
+
+        let fixtures = Fixtures::simkl();
+        resolve_server(&fixtures.client().with_settings(settings.clone()), &loki_episode_info()).unwrap();
+        assert!(path.exists());
+
+        // A fresh client, as after a restart, starts from the file.
+        let fixtures = Fixtures::simkl();
+        let client = fixtures.client().with_settings(settings.clone());
+        resolve_server(&client, &loki_episode_info()).unwrap();
+        assert_eq!(fixtures.requested("https://ww2.moviesapi.to/tv/84958/1/1"), 0);
+
+        // Each provider has its own keys, scoped to the host they came from.
+        client.cache().store_keys(&settings, "vidsrc", "https://vidsrc.example", &vec![String::from("key")]);
+        client.cache().invalidate_keys(&settings, Some("moviesapi"));
+
+        let cache = Fixtures::simkl().client().with_settings(settings.clone());
+        let keys: Option<Vec<String>> = cache.cache().keys(&settings, "vidsrc", "https://vidsrc.example");
+        assert_eq!(keys, Some(vec![String::from("key")]));
+        assert!(cache.cache().keys::<Vec<String>>(&settings, "vidsrc", "https://other.example").is_none());
+        assert!(cache.cache().keys::<serde_json::Value>(&settings, "moviesapi", &settings.server_base_url).is_none());
+
+        cache.cache().invalidate_keys(&settings, None);
+        assert!(!path.exists());
+    }
+
+
+    #[test]
+    fn test_response_cache() {
+        use crate::cache::{normalize_key, Endpoint};
+        use crate::search::{search_titles, MediaType};
+
+        let fixtures = Fixtures::simkl();
+        let client = fixtures.client();
+        let search = |query: &str, bypass: bool| client.cache().get_or_fetch(client.settings(), Endpoint::Search, &normalize_key(query), bypass, || {
+            search_titles(&client, query, MediaType::All)
+        }).unwrap();
+
+        let url = "https://simkl.com/ajax/full/search.php?s=loki&type=tv&sort=0";
+
+        assert_eq!(search("loki", false).len(), 3);
+        assert_eq!(search("  LOKI ", false).len(), 3);
+        assert_eq!(fixtures.requested(url), 1);
+
+        search("loki", true);
+        assert_eq!(fixtures.requested(url), 2);
+
+        client.cache().clear(client.settings(), Some(Endpoint::Search));
+        search("loki", false);
+        assert_eq!(fixtures.requested(url), 3);
+    }
+
+    #[test]
+    fn test_response_cache_per_host() {
+        use crate::cache::Endpoint;
+        use crate::get_episode_list::title_page;
+
+        let fixtures = Fixtures::simkl();
+        let client = fixtures.client();
+        title_page(&client, "/tv/1074318/loki", false).unwrap();
+
+        // The same title on a mirror of simkl is not served from what the first host answered.
+        let mirror = client.clone().with_settings(Settings {
+            source_base_url: String::from("https://simkl.mirror.test"),
+            ..client.settings().clone()
+        });
+        assert!(mirror.cache().lookup(mirror.settings(), Endpoint::TitlePage, "/tv/1074318/loki").is_none());
+        assert!(client.cache().lookup(client.settings(), Endpoint::TitlePage, "/tv/1074318/loki").is_some());
+
+        let entries = client.cache().entries(client.settings());
+        assert_eq!(entries[0].base_url, "https://simkl.com");
+    }
+
+    #[test]
+    fn test_response_cache_eviction() {
+        use crate::cache::Endpoint;
+
+        let settings = Settings {
+            cache_capacity: 2,
+            ..Settings::default()
+        };
+        let client = Fixtures::new().client().with_settings(settings.clone());
+        let fetch = |key: &str| client.cache().get_or_fetch(&settings, Endpoint::Search, key, false, || Ok(key.to_string())).unwrap();
+
+        fetch("a");
+        fetch("b");
+        client.cache().lookup(&settings, Endpoint::Search, "a");
+        fetch("c");
+
+        let keys = client.cache().entries(&settings).into_iter().map(|entry| entry.key).collect::<Vec<_>>();
+        assert_eq!(keys, ["c", "a"]);
+    }
+
+    #[test]
+    fn test_response_cache_on_disk() {
+        use crate::cache::Endpoint;
+        use crate::get_episode_list::{episode_list, EpisodeList};
+
+        let dir = std::env::temp_dir().join(format!("chlaty-moviesapi-cache-{}", std::process::id()));
+        let settings = Settings {
+            cache_dir: Some(dir.to_string_lossy().into_owned()),
+            ..Settings::default()
+        };
+        let key = decode(LOKI_ID).unwrap().into_owned();
+        let url = "https://simkl.com/tv/1074318/loki/episodes";
+
+        let fixtures = Fixtures::simkl();
+        let client = fixtures.client().with_settings(settings.clone());
+        client.cache().get_or_fetch(&settings, Endpoint::EpisodeList, &key, false, || episode_list(&client, LOKI_ID)).unwrap();
+
+        // A fresh client, as after a restart, starts from the directory.
+        let fixtures = Fixtures::simkl();
+        let client = fixtures.client().with_settings(settings.clone());
+        let cached: EpisodeList = client.cache().get_or_fetch(&settings, Endpoint::EpisodeList, &key, false, || episode_list(&client, LOKI_ID)).unwrap();
+        assert!(cached.found());
+        assert_eq!(fixtures.requested(url), 0);
+
+        // Files the library did not write are left alone, even when they look alike.
+        let foreign = ["settings.json", "episode_list-notes.json", "moviesapi-episode_list-draft.json"];
+        for name in foreign {
+            fs::write(dir.join(name), "{}").unwrap();
+        }
+
+        client.cache().clear(&settings, None);
+        let mut left = fs::read_dir(&dir).unwrap()
+            .map(|file| file.unwrap().file_name().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        left.sort();
+        assert_eq!(left, ["episode_list-notes.json", "moviesapi-episode_list-draft.json", "settings.json"]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_response_cache_dir_pruned() {
+        use std::fs::File;
+        use std::time::{Duration, SystemTime};
+        use crate::cache::{normalize_key, Endpoint};
+        use crate::search::{search_titles, MediaType};
+
+        let dir = std::env::temp_dir().join(format!("chlaty-moviesapi-cache-pruned-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let settings = Settings {
+            cache_dir: Some(dir.to_string_lossy().into_owned()),
+            cache_capacity: 2,
+            ..Settings::default()
+        };
+
+        // Left behind by earlier runs: one long expired, two still fresh, plus a file that is not ours.
+        let now = SystemTime::now();
+        let earlier = [
+            ("moviesapi-search-00000000000000000000000000000001.json", now - Duration::from_secs(24 * 60 * 60)),
+            ("moviesapi-search-00000000000000000000000000000002.json", now - Duration::from_secs(20)),
+            ("moviesapi-search-00000000000000000000000000000003.json", now - Duration::from_secs(10)),
+            ("settings.json", now - Duration::from_secs(24 * 60 * 60)),
+        ];
+        for (name, modified) in earlier {
+            fs::write(dir.join(name), "{}").unwrap();
+            File::options().write(true).open(dir.join(name)).unwrap().set_modified(modified).unwrap();
+        }
+
+        let client = Fixtures::simkl().client().with_settings(settings.clone());
+        client.cache().get_or_fetch(&settings, Endpoint::Search, &normalize_key("loki"), false, || {
+            search_titles(&client, "loki", MediaType::All)
+        }).unwrap();
+
+        // The expired file is gone, and only the `cache_capacity` newest are kept.
+        let mut left = fs::read_dir(&dir).unwrap()
+            .map(|file| file.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| name != "settings.json")
+            .collect::<Vec<_>>();
+        left.sort();
+        assert_eq!(left.len(), 2);
+        assert!(left.contains(&String::from("moviesapi-search-00000000000000000000000000000003.json")));
+        assert!(dir.join("settings.json").exists());
+
+        // A stale entry found by a lookup is deleted on the spot.
+        std::thread::sleep(Duration::from_millis(5));
+        let stale = Settings { search_cache_ttl_ms: 1, ..settings };
+        assert!(client.cache().lookup(&stale, Endpoint::Search, &normalize_key("loki")).is_none());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,16 +1,18 @@
 use aes::Aes256;
 use base64::{engine::general_purpose::STANDARD, Engine};
-use cbc::Encryptor;
-use cipher::{BlockEncryptMut, KeyIvInit};
+use cbc::{Decryptor, Encryptor};
+use cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
 use md5::{Digest, Md5};
 use rand::{TryRngCore, rngs::OsRng};
 use cbc::cipher::block_padding::Pkcs7; 
 
-pub fn encrypt(payload: &str, password: &str) -> String {
+use crate::error::{Error, Result};
+
+pub fn encrypt(payload: &str, password: &str) -> Result<String> {
     let payload = payload.as_bytes();
     let password = password.as_bytes();
     let mut salt = [0u8; 8];
-    OsRng.try_fill_bytes(&mut salt).unwrap();
+    OsRng.try_fill_bytes(&mut salt).map_err(|e| Error::Encryption(e.to_string()))?;
 
     // derive key+iv with EVP_BytesToKey (MD5) ...
     let (key, iv) = evp_bytes_to_key(password, &salt);
@@ -23,7 +25,7 @@ pub fn encrypt(payload: &str, password: &str) -> String {
     // encrypt in place
     let n = Encryptor::<Aes256>::new(key.as_slice().into(), iv.as_slice().into())
         .encrypt_padded_mut::<Pkcs7>(&mut buf, payload.len())
-        .unwrap()
+        .map_err(|e| Error::Encryption(e.to_string()))?
         .len();
 
     buf.truncate(n);
@@ -33,7 +35,26 @@ pub fn encrypt(payload: &str, password: &str) -> String {
     out.extend_from_slice(&salt);
     out.extend_from_slice(&buf);
 
-    return STANDARD.encode(out);
+    Ok(STANDARD.encode(out))
+}
+
+/// Reverse `encrypt`: base64 of `Salted__`, the salt and the ciphertext, back to the payload.
+pub fn decrypt(encrypted: &str, password: &str) -> Result<String> {
+    let encrypted = STANDARD.decode(encrypted).map_err(|e| Error::Encryption(e.to_string()))?;
+
+    let (salt, buf) = match encrypted.strip_prefix(b"Salted__") {
+        Some(salted) if salted.len() >= 8 => salted.split_at(8),
+        _ => return Err(Error::Encryption(String::from("Missing `Salted__` header"))),
+    };
+
+    let (key, iv) = evp_bytes_to_key(password.as_bytes(), salt);
+
+    let mut buf = buf.to_vec();
+    let payload = Decryptor::<Aes256>::new(key.as_slice().into(), iv.as_slice().into())
+        .decrypt_padded_mut::<Pkcs7>(&mut buf)
+        .map_err(|e| Error::Encryption(e.to_string()))?;
+
+    String::from_utf8(payload.to_vec()).map_err(|e| Error::Encryption(e.to_string()))
 }
 
 /// EVP_BytesToKey derivation
@@ -0,0 +1,185 @@
+use std::any::Any;
+use std::ffi::{c_void, CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe, UnwindSafe};
+use std::time::Duration;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::json;
+
+use crate::client::Client;
+use crate::error::{Error, Result};
+use crate::runtime;
+
+
+/// Called once with the envelope of an async call. The host owns `result` and releases it with `free_ptr`.
+pub type Callback = extern "C" fn(result: *const c_char, user_data: *mut c_void);
+
+/// Typed body of an entry point, shared by its blocking and async exports.
+pub type Handler<A, T> = fn(A, &Client) -> Result<T>;
+
+
+/// Read and deserialize the JSON arguments passed by the host.
+pub fn parse_arguments<T: DeserializeOwned>(arguments_ptr: *const c_char) -> Result<T> {
+    parse_owned_arguments(copy_arguments(arguments_ptr).as_deref())
+}
+
+fn copy_arguments(arguments_ptr: *const c_char) -> Option<String> {
+    if arguments_ptr.is_null() {
+        return None;
+    }
+
+    Some(unsafe { CStr::from_ptr(arguments_ptr) }.to_string_lossy().into_owned())
+}
+
+fn parse_owned_arguments<T: DeserializeOwned>(raw: Option<&str>) -> Result<T> {
+    let raw = raw.ok_or_else(|| Error::InvalidArguments(String::from("Expected 1 argument.")))?;
+
+    serde_json::from_str(raw).map_err(|e| Error::InvalidArguments(e.to_string()))
+}
+
+/// The shared client, bounded by the configured `deadline_ms` for the current call.
+pub fn client() -> Result<Client> {
+    let client = Client::shared()?;
+    let deadline = Duration::from_millis(client.settings().deadline_ms);
+
+    Ok(client.with_deadline(deadline))
+}
+
+/// Blocking export: parse the arguments, run `handler` and return its envelope.
+pub fn call<A, T>(label: &str, arguments_ptr: *const c_char, handler: Handler<A, T>) -> *const c_char
+where
+    A: DeserializeOwned,
+    T: Serialize,
+{
+    respond(label, || {
+        let args: A = parse_arguments(arguments_ptr)?;
+
+        handler(args, &client()?)
+    })
+}
+
+/// Async export: copy the arguments, queue `handler` on the worker pool and return its handle right away.
+///
+/// Returns `0` when there is no callback to report to. Without a worker to run on, the callback gets an `INTERNAL` envelope right away.
+pub fn call_async<A, T>(
+    label: &'static str,
+    arguments_ptr: *const c_char,
+    callback: Option<Callback>,
+    user_data: *mut c_void,
+    handler: Handler<A, T>,
+) -> u64
+where
+    A: DeserializeOwned + 'static,
+    T: Serialize + 'static,
+{
+    let Some(callback) = callback else {
+        return 0;
+    };
+
+    let arguments = copy_arguments(arguments_ptr);
+    let user_data = UserData(user_data);
+    let (handle, token) = runtime::register(Box::new(move || {
+        callback(into_raw(failure(&Error::Cancelled)), user_data.get());
+    }));
+
+    let spawned = runtime::spawn(Box::new(move || {
+        // Cancelled while queued: `cancel_request` already reported it.
+        if !runtime::start(handle) {
+            return;
+        }
+
+        let mut body = String::new();
+
+        if !token.is_cancelled() {
+            body = respond_json(label, AssertUnwindSafe(|| {
+                let args: A = parse_owned_arguments(arguments.as_deref())?;
+
+                handler(args, &client()?.with_cancel_token(token.clone()))
+            }));
+        }
+
+        // Whatever the call produced, a cancelled handle only ever reports `CANCELLED`.
+        if token.is_cancelled() {
+            body = failure(&Error::Cancelled);
+        }
+
+        runtime::release(handle);
+        callback(into_raw(body), user_data.get());
+    }));
+
+    if let Err(e) = spawned {
+        eprintln!("[{}] Error: {}", label, e);
+        runtime::release(handle);
+        callback(into_raw(failure(&e)), user_data.get());
+    }
+
+    handle
+}
+
+/// Host pointer handed back untouched to the callback, possibly from another thread.
+#[derive(Clone, Copy)]
+struct UserData(*mut c_void);
+
+// The host is responsible for `user_data` being usable from the worker threads.
+unsafe impl Send for UserData {}
+
+impl UserData {
+    fn get(&self) -> *mut c_void {
+        self.0
+    }
+}
+
+/// Run an entry point and always hand back a JSON envelope, never a null pointer.
+pub fn respond<T, F>(label: &str, f: F) -> *const c_char
+where
+    T: Serialize,
+    F: FnOnce() -> Result<T> + UnwindSafe,
+{
+    into_raw(respond_json(label, f))
+}
+
+fn respond_json<T, F>(label: &str, f: F) -> String
+where
+    T: Serialize,
+    F: FnOnce() -> Result<T> + UnwindSafe,
+{
+    let result = match panic::catch_unwind(f) {
+        Ok(result) => result,
+        Err(payload) => Err(Error::Internal(panic_message(payload))),
+    };
+
+    match result.and_then(|data| serde_json::to_string(&data).map_err(|e| Error::Internal(e.to_string()))) {
+        Ok(body) => body,
+        Err(e) => {
+            eprintln!("[{}] Error: {}", label, e);
+            failure(&e)
+        }
+    }
+}
+
+fn into_raw(body: String) -> *const c_char {
+    CString::new(body)
+        .unwrap_or_else(|_| CString::new(failure(&Error::Internal(String::from("Response contains a NUL byte.")))).unwrap())
+        .into_raw()
+}
+
+pub fn failure(error: &Error) -> String {
+    json!({
+        "status": false,
+        "message": error.to_string(),
+        "data": null,
+        "error": error.info(),
+    }).to_string()
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        String::from("Unknown panic.")
+    }
+}
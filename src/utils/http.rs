@@ -0,0 +1,9 @@
+use reqwest::header::HeaderValue;
+
+use crate::error::{Error, Result};
+
+
+/// Header value built from a runtime setting.
+pub fn header_value(value: &str) -> Result<HeaderValue> {
+    HeaderValue::from_str(value).map_err(|e| Error::InvalidArguments(format!("Invalid header value `{}`: {}", value, e)))
+}
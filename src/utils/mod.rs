@@ -1 +1,3 @@
-pub mod aes;
\ No newline at end of file
+pub mod aes;
+pub(crate) mod ffi;
+pub mod http;
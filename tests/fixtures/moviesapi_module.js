@@ -0,0 +1 @@
+const Zn=Object.freeze({SCRAPIFY_URL:"https://ww2.moviesapi.to/api/scrapify/v1/fetch",ENCRYPTION_KEY:"b7f1c9d2e4a64f0d8e3c5a1b9d7e2f60",PLAYER_API_KEY:"c2f5e8a1d4b7"});function Qn(e){return fetch(Zn.SCRAPIFY_URL,{method:"POST",headers:{"X-Player-Key":Zn.PLAYER_API_KEY},body:JSON.stringify({payload:e})})}export{Qn as f};